@@ -0,0 +1,69 @@
+use facet::{ConstTypeId, Facet};
+
+use crate::facet_value::FacetValue;
+
+/// Holds at most one value per type, keyed by the value's shape, e.g. for
+/// per-request extension data.
+#[derive(Default)]
+pub struct AnyShapeMap {
+    values: hashbrown::HashMap<ConstTypeId, FacetValue>,
+}
+
+impl AnyShapeMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores `value`, returning the previous value of type `T`. A
+    /// [`FacetValue`] is stored under the shape of the value it holds, like
+    /// with [`Self::insert_value`], so it is looked up by that shape rather
+    /// than with `get::<FacetValue>()`.
+    pub fn insert<T: Facet<'static>>(&mut self, value: T) -> Option<T> {
+        self.insert_value(FacetValue::new(value))
+            .and_then(from_value)
+    }
+
+    /// Stores `value` under its own shape, returning the previous value of
+    /// that shape.
+    pub fn insert_value(&mut self, value: FacetValue) -> Option<FacetValue> {
+        self.values.insert(value.shape().id, value)
+    }
+
+    pub fn get<T: Facet<'static>>(&self) -> Option<&T> {
+        self.values.get(&T::SHAPE.id)?.downcast_ref()
+    }
+
+    pub fn get_mut<T: Facet<'static>>(&mut self) -> Option<&mut T> {
+        self.values.get_mut(&T::SHAPE.id)?.downcast_mut()
+    }
+
+    pub fn get_value(&self, shape: &facet::Shape) -> Option<&FacetValue> {
+        self.values.get(&shape.id)
+    }
+
+    pub fn remove<T: Facet<'static>>(&mut self) -> Option<T> {
+        self.values.remove(&T::SHAPE.id).and_then(from_value)
+    }
+
+    pub fn contains<T: Facet<'static>>(&self) -> bool {
+        self.values.contains_key(&T::SHAPE.id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// Unwraps a stored value as a `T`, or hands it back as is if `T` is
+/// [`FacetValue`].
+fn from_value<T: Facet<'static>>(value: FacetValue) -> Option<T> {
+    if T::SHAPE.is_shape(FacetValue::SHAPE) {
+        let value = std::mem::ManuallyDrop::new(value);
+        return Some(unsafe { std::ptr::read(&*value as *const FacetValue as *const T) });
+    }
+    value.downcast().ok()
+}
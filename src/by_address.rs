@@ -0,0 +1,82 @@
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+use facet::{
+    Def, Facet, HashFn, HasherWriteFn, PartialEqFn, PtrConst, PtrMut, Shape, Type, TypeParam,
+    UserType, ValueVTable, value_vtable,
+};
+
+/// Wraps a pointer (`Arc`, `Rc`, `&T`, `Box`, ...) so that as a key it's
+/// hashed and compared by the address it points to rather than by value,
+/// e.g. to deduplicate nodes of an object graph.
+pub struct ByAddress<P>(pub P);
+
+impl<P: Deref> ByAddress<P> {
+    fn addr(&self) -> *const () {
+        &*self.0 as *const P::Target as *const ()
+    }
+}
+
+impl<P: Deref> PartialEq for ByAddress<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr() == other.addr()
+    }
+}
+
+impl<P: Deref> Eq for ByAddress<P> {}
+
+impl<P: Deref> Hash for ByAddress<P> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.addr().hash(state)
+    }
+}
+
+impl<P> Deref for ByAddress<P> {
+    type Target = P;
+
+    fn deref(&self) -> &P {
+        &self.0
+    }
+}
+
+unsafe fn hash<P: Deref>(value: PtrConst<'_>, hasher_this: PtrMut<'_>, write: HasherWriteFn) {
+    let value: &ByAddress<P> = unsafe { value.get() };
+    unsafe { write(hasher_this, &(value.addr() as usize).to_ne_bytes()) }
+}
+
+unsafe fn eq<P: Deref>(left: PtrConst<'_>, right: PtrConst<'_>) -> bool {
+    unsafe { left.get::<ByAddress<P>>() == right.get::<ByAddress<P>>() }
+}
+
+// Safety: the shape describes the wrapper as an opaque sized value, and the
+// hash and eq fns are filled in by hand since `value_vtable!` can't see the
+// impls above for a generic `P`
+unsafe impl<'a, P: Facet<'a> + Deref> Facet<'a> for ByAddress<P> {
+    const VTABLE: &'static ValueVTable = &const {
+        let mut vtable = value_vtable!(ByAddress<P>, |f, opts| {
+            write!(f, "{}", Self::SHAPE.type_identifier)?;
+            if let Some(opts) = opts.for_children() {
+                write!(f, "<")?;
+                (P::SHAPE.vtable.type_name)(f, opts)?;
+                write!(f, ">")
+            } else {
+                write!(f, "<…>")
+            }
+        });
+        vtable.hash = || Some(hash::<P> as HashFn);
+        vtable.partial_eq = || Some(eq::<P> as PartialEqFn);
+        vtable
+    };
+
+    const SHAPE: &'static Shape<'static> = &const {
+        Shape::builder_for_sized::<Self>()
+            .type_identifier("ByAddress")
+            .type_params(&[TypeParam {
+                name: "P",
+                shape: || P::SHAPE,
+            }])
+            .ty(Type::User(UserType::Opaque))
+            .def(Def::Undefined)
+            .build()
+    };
+}
@@ -0,0 +1,152 @@
+use std::hash::BuildHasher;
+
+use facet::{Facet, PtrConst, PtrMut, Shape};
+
+use crate::erased::Erased;
+use crate::erased_hashmap::{ErasedHashMap, ErasedKey, ErasedKeyRef, ErasedValue};
+use crate::storage::{DefaultStorage, StorageBackend};
+
+/// The mirror image of [`crate::FacetDynValueMap`]: values are statically
+/// typed, but the key shape is only known at runtime. Typed accessors check
+/// `K`'s shape against the map's key shape.
+pub struct FacetDynKeyMap<
+    'a,
+    V: Facet<'a>,
+    S = hashbrown::DefaultHashBuilder,
+    B: StorageBackend = DefaultStorage,
+> {
+    hash_map: ErasedHashMap<S, B>,
+    key_shape: &'static Shape<'static>,
+    _marker: std::marker::PhantomData<(V, &'a ())>,
+}
+
+impl<'a, V, S, B> Drop for FacetDynKeyMap<'a, V, S, B>
+where
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    fn drop(&mut self) {
+        unsafe {
+            ErasedHashMap::drop_keys_and_values(&mut self.hash_map, self.key_shape, V::SHAPE);
+        }
+    }
+}
+
+impl<'a, V, S, B> FacetDynKeyMap<'a, V, S, B>
+where
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    pub fn new(key_shape: &'static Shape<'static>) -> Self
+    where
+        S: Default,
+        B: Default,
+    {
+        Self::with_hasher_and_storage(key_shape, S::default(), B::default())
+    }
+
+    pub fn with_hasher_and_storage(
+        key_shape: &'static Shape<'static>,
+        hash_builder: S,
+        storage: B,
+    ) -> Self {
+        Self {
+            hash_map: ErasedHashMap::with_hasher_and_storage(hash_builder, storage),
+            key_shape,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    pub fn key_shape(&self) -> &'static Shape<'static> {
+        self.key_shape
+    }
+
+    /// Inserts `value` under `key` if `K` has the map's key shape, returning
+    /// the old value. Otherwise both are handed back.
+    pub fn insert<K: Facet<'static>>(&mut self, key: K, value: V) -> Result<Option<V>, (K, V)>
+    where
+        S: BuildHasher,
+    {
+        if !K::SHAPE.is_shape(self.key_shape) {
+            return Err((key, value));
+        }
+        let erased_key = ErasedKey(Erased::new(key, self.hash_map.storage()));
+        Ok(unsafe { self.insert_erased_key(erased_key, value) })
+    }
+
+    /// Moves the key at `key` into the map and inserts `value` under it,
+    /// returning the old value.
+    ///
+    /// # Safety
+    /// `key` must point to an initialized value of the map's key shape,
+    /// which must not be used or dropped afterwards
+    pub unsafe fn insert_erased(&mut self, key: PtrMut<'_>, value: V) -> Option<V>
+    where
+        S: BuildHasher,
+    {
+        let size = self.key_shape.layout.sized_layout().unwrap().size();
+        let erased_key = unsafe {
+            ErasedKey(Erased::from_init(
+                self.key_shape,
+                self.hash_map.storage(),
+                |dst| {
+                    std::ptr::copy_nonoverlapping(key.as_byte_ptr(), dst.as_mut_byte_ptr(), size);
+                },
+            ))
+        };
+        unsafe { self.insert_erased_key(erased_key, value) }
+    }
+
+    unsafe fn insert_erased_key(&mut self, key: ErasedKey, value: V) -> Option<V>
+    where
+        S: BuildHasher,
+    {
+        let erased_value = ErasedValue(Erased::new(value, self.hash_map.storage()));
+        let old_erased_value = unsafe { self.hash_map.insert(key, self.key_shape, erased_value) };
+        old_erased_value.map(|old_value| unsafe { old_value.0.into_typed(self.hash_map.storage()) })
+    }
+
+    /// Returns the value for `key`, or `None` if `K` doesn't have the map's
+    /// key shape.
+    pub fn get<K: Facet<'static>>(&self, key: &K) -> Option<&V>
+    where
+        S: BuildHasher,
+    {
+        if !K::SHAPE.is_shape(self.key_shape) {
+            return None;
+        }
+        unsafe { self.get_erased(PtrConst::new(key)) }
+    }
+
+    /// # Safety
+    /// `key` must point to a value of the map's key shape
+    pub unsafe fn get_erased(&self, key: PtrConst<'_>) -> Option<&V>
+    where
+        S: BuildHasher,
+    {
+        let value_storage = self.hash_map.storage().storage_for(V::SHAPE);
+        unsafe {
+            self.hash_map
+                .get(ErasedKeyRef(key), self.key_shape)
+                .map(|value| value.as_ptr(value_storage).get())
+        }
+    }
+
+    /// Removes the entry for `key`, or returns `None` if `K` doesn't have
+    /// the map's key shape.
+    pub fn remove<K: Facet<'static>>(&mut self, key: &K) -> Option<V>
+    where
+        S: BuildHasher,
+    {
+        if !K::SHAPE.is_shape(self.key_shape) {
+            return None;
+        }
+        let key_ref = ErasedKeyRef(PtrConst::new(key));
+        let (mut erased_key, erased_value) =
+            unsafe { self.hash_map.remove(key_ref, self.key_shape)? };
+        if let Some(drop_key) = Erased::drop_fn(self.key_shape, self.hash_map.storage()) {
+            drop_key(&mut erased_key.0);
+        }
+        Some(unsafe { erased_value.0.into_typed(self.hash_map.storage()) })
+    }
+}
@@ -0,0 +1,964 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::mem::MaybeUninit;
+
+use facet::{Def, Facet, FieldFlags, PtrConst, PtrMut, PtrUninit, Shape, Type, UserType};
+
+use crate::facet_hashmap::FacetHashMap;
+use crate::facet_value::FacetValue;
+
+/// A string-keyed map of dynamically typed values, e.g. for configuration.
+/// Nested maps are stored as [`NestedMap`] values.
+pub type DynHashMap<S = hashbrown::DefaultHashBuilder> =
+    FacetHashMap<'static, String, FacetValue, S>;
+
+/// The map type `set_path` creates for missing intermediate path segments.
+pub type NestedMap = HashMap<String, FacetValue>;
+
+/// A way in which a [`DynHashMap`] doesn't match a struct shape. Fields of
+/// nested structs are named by their dotted path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    NotAStruct {
+        shape: &'static Shape<'static>,
+    },
+    MissingField {
+        field: String,
+    },
+    UnknownField {
+        field: String,
+    },
+    MismatchedShape {
+        field: String,
+        expected: &'static Shape<'static>,
+        found: &'static Shape<'static>,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAStruct { shape } => write!(f, "{shape} is not a struct"),
+            Self::MissingField { field } => write!(f, "missing field `{field}`"),
+            Self::UnknownField { field } => write!(f, "unknown field `{field}`"),
+            Self::MismatchedShape {
+                field,
+                expected,
+                found,
+            } => {
+                write!(f, "field `{field}` should be {expected}, found {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Why [`DynHashMap::to_struct`] couldn't build a struct.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// The entries don't match the struct, see [`DynHashMap::validate_against`].
+    Invalid(ValidationError),
+    /// The value for `field` has the right shape but it can't be cloned.
+    NotCloneable {
+        field: String,
+        shape: &'static Shape<'static>,
+    },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(error) => write!(f, "{error}"),
+            Self::NotCloneable { field, shape } => {
+                write!(f, "field `{field}` holds a {shape}, which can't be cloned")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Invalid(error) => error.source(),
+            Self::NotCloneable { .. } => None,
+        }
+    }
+}
+
+/// Why [`DynHashMap::get_as`] couldn't produce a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoerceError {
+    /// Nothing is stored at the path.
+    Missing,
+    /// The stored value can't be converted to the requested shape.
+    Incompatible {
+        from: &'static Shape<'static>,
+        to: &'static Shape<'static>,
+    },
+}
+
+impl fmt::Display for CoerceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "no value at the path"),
+            Self::Incompatible { from, to } => write!(f, "can't convert {from} to {to}"),
+        }
+    }
+}
+
+impl std::error::Error for CoerceError {}
+
+/// How [`DynHashMap::deep_merge`] combines two values that are both lists
+/// of the same shape. Nested maps are always merged key by key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// The incoming list replaces the existing one.
+    ReplaceLists,
+    /// The incoming list's items are appended to the existing one.
+    ConcatLists,
+}
+
+/// Why [`DynHashMap::increment`] couldn't add to a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IncrementError {
+    NotNumeric {
+        shape: &'static Shape<'static>,
+    },
+    /// The sum doesn't fit in the stored value's type.
+    Overflow {
+        shape: &'static Shape<'static>,
+    },
+}
+
+impl fmt::Display for IncrementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotNumeric { shape } => write!(f, "{shape} is not numeric"),
+            Self::Overflow { shape } => write!(f, "the sum overflows {shape}"),
+        }
+    }
+}
+
+impl std::error::Error for IncrementError {}
+
+impl<S: BuildHasher + Default> FacetHashMap<'static, String, FacetValue, S> {
+    /// Copies each field of the struct `value` into an entry named after the
+    /// field, cloning the field through its shape.
+    pub fn from_struct<T: Facet<'static>>(value: &T) -> Result<Self, BuildError> {
+        let mut map = Self::default();
+        unsafe { flatten_struct(&mut map, PtrConst::new(value), T::SHAPE, None)? };
+        Ok(map)
+    }
+
+    /// Like [`Self::from_struct`], but the fields of nested structs become
+    /// entries of their own, keyed by their dotted path (`"server.port"`).
+    pub fn from_struct_flattened<T: Facet<'static>>(value: &T) -> Result<Self, BuildError> {
+        let mut map = Self::default();
+        unsafe { flatten_struct(&mut map, PtrConst::new(value), T::SHAPE, Some(""))? };
+        Ok(map)
+    }
+}
+
+impl<S: BuildHasher> FacetHashMap<'static, String, FacetValue, S> {
+    /// Looks up a dotted path like `"server.port"`, descending through nested
+    /// maps and the fields of struct-shaped values. Returns `None` if the
+    /// path doesn't exist or doesn't lead to a `T`.
+    pub fn get_path<T: Facet<'static>>(&self, path: &str) -> Option<&T> {
+        let (ptr, shape) = self.resolve_path(path)?;
+        unsafe { cast(ptr, shape) }
+    }
+
+    /// Like [`Self::get_path`], but also looks through an `Option<T>`
+    /// value, returning `None` if it's `None`.
+    pub fn get_flat<T: Facet<'static>>(&self, path: &str) -> Option<&T> {
+        let (ptr, shape) = self.resolve_path(path)?;
+        let (ptr, shape) = unsafe { unwrap_value(ptr, shape) };
+        let (ptr, shape) = match shape.def {
+            Def::Option(option_def) => (
+                unsafe { (option_def.vtable.get_value_fn)(ptr)? },
+                option_def.t(),
+            ),
+            _ => (ptr, shape),
+        };
+        unsafe { cast(ptr, shape) }
+    }
+
+    /// Stores `Some(value)` under `key`.
+    pub fn insert_some<T: Facet<'static>>(&mut self, key: &str, value: T) {
+        self.insert(key.to_string(), FacetValue::new(Some(value)));
+    }
+
+    /// Removes the entry for `key` and returns its value if it's a `T` or a
+    /// `Some(T)`. An entry holding anything else, including `None::<T>`, is
+    /// left in place.
+    pub fn take_inner<T: Facet<'static>>(&mut self, key: &str) -> Option<T> {
+        let key = key.to_string();
+        let value = self.get(&key)?;
+        if !value.is::<T>()
+            && !value
+                .downcast_ref::<Option<T>>()
+                .is_some_and(Option::is_some)
+        {
+            return None;
+        }
+
+        match self.remove(&key)?.downcast::<T>() {
+            Ok(value) => Some(value),
+            Err(value) => value.downcast::<Option<T>>().ok().flatten(),
+        }
+    }
+
+    /// Reads the value at a dotted path as a `T`, converting it if it's
+    /// stored as something else: first through `T`'s `try_from`, then by
+    /// formatting the value and parsing the result, so `"8080"` can be read
+    /// as a `u16` and `3` as an `f64`.
+    pub fn get_as<T: Facet<'static>>(&self, path: &str) -> Result<T, CoerceError> {
+        let (ptr, shape) = self.resolve_path(path).ok_or(CoerceError::Missing)?;
+        let (ptr, shape) = unsafe { unwrap_value(ptr, shape) };
+        unsafe { coerce(ptr, shape) }
+    }
+
+    /// Like [`Self::get_as`], but falls back to `default` if the path is
+    /// missing or its value can't be converted.
+    pub fn get_or<T: Facet<'static>>(&self, path: &str, default: T) -> T {
+        self.get_as(path).unwrap_or(default)
+    }
+
+    /// Adds `delta` to the numeric value for `key`, whatever its primitive
+    /// type, or stores `delta` as an `i64` if there's no value yet. Takes a
+    /// single lookup.
+    pub fn increment(&mut self, key: &str, delta: i64) -> Result<(), IncrementError> {
+        let mut result = Ok(());
+        self.extend_with(
+            [(key.to_string(), FacetValue::new(delta))],
+            |existing, _| result = add_to_numeric(existing, delta),
+        );
+        result
+    }
+
+    fn resolve_path(&self, path: &str) -> Option<(PtrConst<'_>, &'static Shape<'static>)> {
+        let mut segments = path.split('.');
+        let value = self.get(&segments.next()?.to_string())?;
+
+        let mut ptr = PtrConst::new(value);
+        let mut shape = FacetValue::SHAPE;
+        for segment in segments {
+            (ptr, shape) = unsafe { child(ptr, shape, segment)? };
+        }
+        Some((ptr, shape))
+    }
+
+    /// Like [`Self::get_path`], but returns a mutable reference.
+    pub fn get_path_mut<T: Facet<'static>>(&mut self, path: &str) -> Option<&mut T> {
+        let mut segments = path.split('.');
+        let value = self.get_mut(&segments.next()?.to_string())?;
+
+        let mut ptr = PtrMut::new(value);
+        let mut shape = FacetValue::SHAPE;
+        for segment in segments {
+            (ptr, shape) = unsafe { child_mut(ptr, shape, segment, false)? };
+        }
+
+        unsafe { cast_mut(ptr, shape) }
+    }
+
+    /// Sets the value at a dotted path like `"server.port"`, creating empty
+    /// [`NestedMap`]s for missing intermediate segments. A struct field can
+    /// only be set to a value of the field's own type. Returns `value` back
+    /// if the path can't be set.
+    pub fn set_path<T: Facet<'static>>(&mut self, path: &str, value: T) -> Result<(), T> {
+        let Some((parent_path, last)) = path.rsplit_once('.') else {
+            self.insert(path.to_string(), FacetValue::new(value));
+            return Ok(());
+        };
+
+        let mut segments = parent_path.split('.');
+        let first = segments.next().unwrap().to_string();
+        if self.get(&first).is_none() {
+            self.insert(first.clone(), FacetValue::new(NestedMap::new()));
+        }
+
+        let mut ptr = PtrMut::new(self.get_mut(&first).unwrap());
+        let mut shape = FacetValue::SHAPE;
+        for segment in segments {
+            match unsafe { child_mut(ptr, shape, segment, true) } {
+                Some(child) => (ptr, shape) = child,
+                None => return Err(value),
+            }
+        }
+
+        unsafe { set_child(ptr, shape, last, value) }
+    }
+
+    /// Checks that the entries match the fields of the struct `shape`: every
+    /// field without a default is present, there are no unknown keys, and
+    /// each value has its field's shape. Nested maps are checked against
+    /// struct-shaped fields recursively.
+    pub fn validate_against(
+        &self,
+        shape: &'static Shape<'static>,
+    ) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        validate_fields(
+            shape,
+            &mut self.iter().map(|(key, value)| (key.as_str(), value)),
+            &|key| self.get(&key.to_string()),
+            "",
+            &mut errors,
+        );
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Builds a `T` from the entries, cloning each value into the struct
+    /// field of the same name. Missing fields with a default are defaulted
+    /// and nested maps are built into struct-shaped fields recursively.
+    /// Unknown keys are ignored unless `T` denies unknown fields.
+    pub fn to_struct<T: Facet<'static>>(&self) -> Result<T, BuildError> {
+        let mut value = MaybeUninit::<T>::uninit();
+        unsafe {
+            build_struct(
+                T::SHAPE,
+                &mut self.iter().map(|(key, _)| key.as_str()),
+                &|key| self.get(&key.to_string()),
+                "",
+                PtrUninit::new(value.as_mut_ptr()),
+            )?;
+            Ok(value.assume_init())
+        }
+    }
+
+    /// Moves the entries of `other` into this map. Where both have a nested
+    /// map ([`NestedMap`] or [`DynHashMap`]) under a key, they're merged
+    /// recursively, and lists are combined according to `strategy`; any
+    /// other value in `other` replaces the existing one.
+    pub fn deep_merge<S2: BuildHasher>(
+        &mut self,
+        mut other: DynHashMap<S2>,
+        strategy: MergeStrategy,
+    ) {
+        let keys: Vec<String> = other.iter().map(|(key, _)| key.clone()).collect();
+        for key in keys {
+            let incoming = other.remove(&key).unwrap();
+            match self.get_mut(&key) {
+                Some(existing) => deep_merge_value(existing, incoming, strategy),
+                None => {
+                    self.insert(key, incoming);
+                }
+            }
+        }
+    }
+
+    /// Applies an RFC 7386 JSON merge patch: null values remove entries,
+    /// nested maps are merged recursively and anything else replaces the
+    /// existing value.
+    ///
+    /// Panics if a value that has to be copied from `patch` has a shape that
+    /// can't be cloned.
+    pub fn merge_patch<S2>(&mut self, patch: &DynHashMap<S2>) {
+        apply_patch(self, &mut patch.iter());
+    }
+}
+
+fn validate_fields<'m>(
+    shape: &'static Shape<'static>,
+    entries: &mut dyn Iterator<Item = (&'m str, &'m FacetValue)>,
+    get: &dyn Fn(&str) -> Option<&'m FacetValue>,
+    prefix: &str,
+    errors: &mut Vec<ValidationError>,
+) {
+    let Type::User(UserType::Struct(struct_type)) = shape.ty else {
+        errors.push(ValidationError::NotAStruct { shape });
+        return;
+    };
+
+    for field in struct_type.fields {
+        let path = format!("{prefix}{}", field.name);
+        let Some(value) = get(field.name) else {
+            if !field_has_default(shape, field) {
+                errors.push(ValidationError::MissingField { field: path });
+            }
+            continue;
+        };
+
+        let field_shape = field.shape();
+        if field_shape.is_shape(FacetValue::SHAPE) || value.shape().is_shape(field_shape) {
+            continue;
+        }
+        match (value.downcast_ref::<NestedMap>(), field_shape.ty) {
+            (Some(nested_map), Type::User(UserType::Struct(_))) => validate_fields(
+                field_shape,
+                &mut nested_map.iter().map(|(key, value)| (key.as_str(), value)),
+                &|key| nested_map.get(key),
+                &format!("{path}."),
+                errors,
+            ),
+            _ => errors.push(ValidationError::MismatchedShape {
+                field: path,
+                expected: field_shape,
+                found: value.shape(),
+            }),
+        }
+    }
+
+    for (key, _) in entries {
+        if !struct_type.fields.iter().any(|field| field.name == key) {
+            errors.push(ValidationError::UnknownField {
+                field: format!("{prefix}{key}"),
+            });
+        }
+    }
+}
+
+/// Inserts the fields of the struct at `ptr` into `map`. If `prefix` is set,
+/// nested structs are flattened into dotted keys starting with it.
+unsafe fn flatten_struct<S: BuildHasher>(
+    map: &mut DynHashMap<S>,
+    ptr: PtrConst<'_>,
+    shape: &'static Shape<'static>,
+    prefix: Option<&str>,
+) -> Result<(), BuildError> {
+    let Type::User(UserType::Struct(struct_type)) = shape.ty else {
+        return Err(BuildError::Invalid(ValidationError::NotAStruct { shape }));
+    };
+
+    for field in struct_type.fields {
+        let key = format!("{}{}", prefix.unwrap_or_default(), field.name);
+        let field_ptr = unsafe { ptr.field(field.offset) };
+        let field_shape = field.shape();
+
+        if let (Some(_), Type::User(UserType::Struct(_))) = (prefix, field_shape.ty) {
+            unsafe { flatten_struct(map, field_ptr, field_shape, Some(&format!("{key}.")))? };
+            continue;
+        }
+
+        let value = unsafe { FacetValue::clone_from_ptr(field_ptr, field_shape) }.ok_or(
+            BuildError::NotCloneable {
+                field: key.clone(),
+                shape: field_shape,
+            },
+        )?;
+        map.insert(key, value);
+    }
+
+    Ok(())
+}
+
+/// Initializes the struct `shape` at `target` from the entries. On error,
+/// any fields that were already initialized are dropped again.
+unsafe fn build_struct<'m>(
+    shape: &'static Shape<'static>,
+    keys: &mut dyn Iterator<Item = &'m str>,
+    get: &dyn Fn(&str) -> Option<&'m FacetValue>,
+    prefix: &str,
+    target: PtrUninit<'_>,
+) -> Result<(), BuildError> {
+    let Type::User(UserType::Struct(struct_type)) = shape.ty else {
+        return Err(BuildError::Invalid(ValidationError::NotAStruct { shape }));
+    };
+
+    if shape.has_deny_unknown_fields_attr() {
+        for key in keys {
+            if !struct_type.fields.iter().any(|field| field.name == key) {
+                return Err(BuildError::Invalid(ValidationError::UnknownField {
+                    field: format!("{prefix}{key}"),
+                }));
+            }
+        }
+    }
+
+    for (index, field) in struct_type.fields.iter().enumerate() {
+        let field_target = unsafe { target.field_uninit_at(field.offset) };
+        let result = unsafe { build_field(shape, field, get, prefix, field_target) };
+
+        if let Err(error) = result {
+            for initialized_field in &struct_type.fields[..index] {
+                if let Some(drop_in_place) = (initialized_field.shape().vtable.drop_in_place)() {
+                    unsafe {
+                        drop_in_place(target.field_init_at(initialized_field.offset));
+                    }
+                }
+            }
+            return Err(error);
+        }
+    }
+
+    Ok(())
+}
+
+unsafe fn build_field<'m>(
+    shape: &'static Shape<'static>,
+    field: &'static facet::Field<'static>,
+    get: &dyn Fn(&str) -> Option<&'m FacetValue>,
+    prefix: &str,
+    target: PtrUninit<'_>,
+) -> Result<(), BuildError> {
+    let path = format!("{prefix}{}", field.name);
+    let field_shape = field.shape();
+
+    let Some(value) = get(field.name) else {
+        let default_in_place = field
+            .vtable
+            .default_fn
+            .or_else(|| (field_shape.vtable.default_in_place)())
+            .filter(|_| field_has_default(shape, field));
+        return match default_in_place {
+            Some(default_in_place) => {
+                unsafe { default_in_place(target) };
+                Ok(())
+            }
+            None => Err(BuildError::Invalid(ValidationError::MissingField {
+                field: path,
+            })),
+        };
+    };
+
+    if field_shape.is_shape(FacetValue::SHAPE) {
+        let value = value.try_clone().ok_or(BuildError::NotCloneable {
+            field: path,
+            shape: value.shape(),
+        })?;
+        unsafe { target.put(value) };
+        return Ok(());
+    }
+
+    if value.shape().is_shape(field_shape) {
+        let clone_into = (field_shape.vtable.clone_into)().ok_or(BuildError::NotCloneable {
+            field: path,
+            shape: field_shape,
+        })?;
+        unsafe { clone_into(value.as_ptr(), target) };
+        return Ok(());
+    }
+
+    match (value.downcast_ref::<NestedMap>(), field_shape.ty) {
+        (Some(nested_map), Type::User(UserType::Struct(_))) => unsafe {
+            build_struct(
+                field_shape,
+                &mut nested_map.keys().map(String::as_str),
+                &|key| nested_map.get(key),
+                &format!("{path}."),
+                target,
+            )
+        },
+        _ => Err(BuildError::Invalid(ValidationError::MismatchedShape {
+            field: path,
+            expected: field_shape,
+            found: value.shape(),
+        })),
+    }
+}
+
+/// Whether `field` of the struct `shape` can be left out and defaulted.
+fn field_has_default(shape: &Shape, field: &facet::Field) -> bool {
+    field.flags.contains(FieldFlags::DEFAULT)
+        || field.vtable.default_fn.is_some()
+        || shape.has_default_attr()
+        || matches!(field.shape().def, Def::Option(_))
+}
+
+/// A map a merge patch can be applied to: a [`DynHashMap`] or a
+/// [`NestedMap`].
+trait PatchTarget {
+    fn value_mut(&mut self, key: &str) -> Option<&mut FacetValue>;
+    fn insert_value(&mut self, key: String, value: FacetValue);
+    fn remove_value(&mut self, key: &str);
+}
+
+impl PatchTarget for NestedMap {
+    fn value_mut(&mut self, key: &str) -> Option<&mut FacetValue> {
+        self.get_mut(key)
+    }
+
+    fn insert_value(&mut self, key: String, value: FacetValue) {
+        self.insert(key, value);
+    }
+
+    fn remove_value(&mut self, key: &str) {
+        self.remove(key);
+    }
+}
+
+impl<S: BuildHasher> PatchTarget for DynHashMap<S> {
+    fn value_mut(&mut self, key: &str) -> Option<&mut FacetValue> {
+        self.get_mut(&key.to_string())
+    }
+
+    fn insert_value(&mut self, key: String, value: FacetValue) {
+        self.insert(key, value);
+    }
+
+    fn remove_value(&mut self, key: &str) {
+        self.remove(&key.to_string());
+    }
+}
+
+type PatchEntries<'p> = dyn Iterator<Item = (&'p String, &'p FacetValue)> + 'p;
+
+/// The entries of a patch value that is a nested map.
+fn patch_map_entries(value: &FacetValue) -> Option<Box<PatchEntries<'_>>> {
+    if let Some(map) = value.downcast_ref::<NestedMap>() {
+        return Some(Box::new(map.iter()));
+    }
+    let map = value.downcast_ref::<DynHashMap>()?;
+    Some(Box::new(map.iter()))
+}
+
+fn apply_patch(target: &mut dyn PatchTarget, patch: &mut PatchEntries<'_>) {
+    for (key, patch_value) in patch {
+        if patch_value.is_null() {
+            target.remove_value(key);
+            continue;
+        }
+        let Some(mut patch_entries) = patch_map_entries(patch_value) else {
+            target.insert_value(key.clone(), clone_patch_value(key, patch_value));
+            continue;
+        };
+
+        let is_map = |value: &mut FacetValue| value.is::<NestedMap>() || value.is::<DynHashMap>();
+        if !target.value_mut(key).is_some_and(is_map) {
+            let empty = if patch_value.is::<DynHashMap>() {
+                FacetValue::new(DynHashMap::<hashbrown::DefaultHashBuilder>::default())
+            } else {
+                FacetValue::new(NestedMap::new())
+            };
+            target.insert_value(key.clone(), empty);
+        }
+        let nested = target.value_mut(key).unwrap();
+        let nested: &mut dyn PatchTarget = if nested.is::<NestedMap>() {
+            nested.downcast_mut::<NestedMap>().unwrap()
+        } else {
+            nested.downcast_mut::<DynHashMap>().unwrap()
+        };
+        apply_patch(nested, &mut patch_entries);
+    }
+}
+
+fn deep_merge_value(existing: &mut FacetValue, incoming: FacetValue, strategy: MergeStrategy) {
+    if let Some(existing_map) = existing.downcast_mut::<NestedMap>() {
+        match incoming.downcast::<NestedMap>() {
+            Ok(incoming_map) => {
+                for (key, incoming) in incoming_map {
+                    match existing_map.get_mut(&key) {
+                        Some(existing) => deep_merge_value(existing, incoming, strategy),
+                        None => {
+                            existing_map.insert(key, incoming);
+                        }
+                    }
+                }
+            }
+            Err(incoming) => *existing = incoming,
+        }
+        return;
+    }
+
+    if let Some(existing_map) = existing.downcast_mut::<DynHashMap>() {
+        match incoming.downcast::<DynHashMap>() {
+            Ok(incoming_map) => existing_map.deep_merge(incoming_map, strategy),
+            Err(incoming) => *existing = incoming,
+        }
+        return;
+    }
+
+    if strategy == MergeStrategy::ConcatLists
+        && existing.shape().is_shape(incoming.shape())
+        && unsafe { concat_list(existing.as_mut_ptr(), incoming.as_ptr(), incoming.shape()) }
+    {
+        return;
+    }
+
+    *existing = incoming;
+}
+
+/// [`deep_merge_value`] for a typed value, which can only hold nested maps
+/// if it is a [`FacetValue`].
+pub(crate) fn deep_merge_typed<'a, V: Facet<'a>>(
+    existing: &mut V,
+    incoming: V,
+    strategy: MergeStrategy,
+) {
+    if V::SHAPE.is_shape(FacetValue::SHAPE) {
+        let existing = unsafe { &mut *(existing as *mut V as *mut FacetValue) };
+        let incoming = std::mem::ManuallyDrop::new(incoming);
+        let incoming = unsafe { std::ptr::read(&*incoming as *const V as *const FacetValue) };
+        deep_merge_value(existing, incoming, strategy);
+        return;
+    }
+
+    if strategy == MergeStrategy::ConcatLists
+        && unsafe { concat_list(PtrMut::new(existing), PtrConst::new(&incoming), V::SHAPE) }
+    {
+        return;
+    }
+
+    *existing = incoming;
+}
+
+/// Appends clones of the items of the `incoming` list to the `existing`
+/// one. Returns false without changing anything if `shape` isn't a list
+/// that can be pushed to, or its items can't be cloned.
+unsafe fn concat_list(
+    existing: PtrMut<'_>,
+    incoming: PtrConst<'_>,
+    shape: &'static Shape<'static>,
+) -> bool {
+    let Def::List(list_def) = shape.def else {
+        return false;
+    };
+    let Some(push) = list_def.vtable.push else {
+        return false;
+    };
+    let item_shape = list_def.t();
+    let len = unsafe { (list_def.vtable.len)(incoming) };
+    let items: Option<Vec<FacetValue>> = (0..len)
+        .map(|index| unsafe {
+            let item = (list_def.vtable.get)(incoming, index).unwrap();
+            FacetValue::clone_from_ptr(item, item_shape)
+        })
+        .collect();
+    let Some(items) = items else {
+        return false;
+    };
+    for item in items {
+        unsafe { item.move_out(|item| push(existing, item)) };
+    }
+    true
+}
+
+fn clone_patch_value(key: &str, patch_value: &FacetValue) -> FacetValue {
+    patch_value.try_clone().unwrap_or_else(|| {
+        panic!(
+            "patch value for `{key}` has shape `{}`, which can't be cloned",
+            patch_value.shape()
+        )
+    })
+}
+
+fn add_to_numeric(value: &mut FacetValue, delta: i64) -> Result<(), IncrementError> {
+    let shape = value.shape();
+    // the sum is worked out in i128, which holds every value of these types
+    // plus any delta, and then narrowed back
+    macro_rules! add_integer {
+        ($($ty:ty),*) => {
+            $(
+                if let Some(n) = value.downcast_mut::<$ty>() {
+                    let sum = <$ty>::try_from(*n as i128 + delta as i128).ok();
+                    *n = sum.ok_or(IncrementError::Overflow { shape })?;
+                    return Ok(());
+                }
+            )*
+        };
+    }
+    add_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+    if let Some(n) = value.downcast_mut::<i128>() {
+        *n = n
+            .checked_add(delta.into())
+            .ok_or(IncrementError::Overflow { shape })?;
+        return Ok(());
+    }
+    if let Some(n) = value.downcast_mut::<u128>() {
+        *n = n
+            .checked_add_signed(delta.into())
+            .ok_or(IncrementError::Overflow { shape })?;
+        return Ok(());
+    }
+
+    if let Some(n) = value.downcast_mut::<f64>() {
+        *n += delta as f64;
+    } else if let Some(n) = value.downcast_mut::<f32>() {
+        *n += delta as f32;
+    } else {
+        return Err(IncrementError::NotNumeric { shape });
+    }
+    Ok(())
+}
+
+unsafe fn coerce<T: Facet<'static>>(
+    ptr: PtrConst<'_>,
+    shape: &'static Shape<'static>,
+) -> Result<T, CoerceError> {
+    let mut value = MaybeUninit::<T>::uninit();
+    let target = PtrUninit::new(value.as_mut_ptr());
+
+    if shape.is_shape(T::SHAPE)
+        && let Some(clone_into) = (shape.vtable.clone_into)()
+    {
+        unsafe { clone_into(ptr, target) };
+        return Ok(unsafe { value.assume_init() });
+    }
+
+    // `try_from` moves out of its source when it succeeds, so it gets a clone
+    // rather than the value the map owns
+    if let Some(try_from) = (T::SHAPE.vtable.try_from)()
+        && let Some(source) = unsafe { FacetValue::clone_from_ptr(ptr, shape) }
+        && unsafe { try_from(source.as_ptr(), shape, target) }.is_ok()
+    {
+        unsafe { source.move_out(|_| {}) };
+        return Ok(unsafe { value.assume_init() });
+    }
+
+    let formatted = if shape.is_shape(String::SHAPE) {
+        Some(unsafe { ptr.get::<String>() }.clone())
+    } else {
+        (shape.vtable.display)().map(|display| {
+            struct Formatted<'v>(PtrConst<'v>, facet::DisplayFn);
+            impl std::fmt::Display for Formatted<'_> {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    unsafe { (self.1)(self.0, f) }
+                }
+            }
+            Formatted(ptr, display).to_string()
+        })
+    };
+    if let (Some(formatted), Some(parse)) = (formatted, (T::SHAPE.vtable.parse)())
+        && unsafe { parse(&formatted, target) }.is_ok()
+    {
+        return Ok(unsafe { value.assume_init() });
+    }
+
+    Err(CoerceError::Incompatible {
+        from: shape,
+        to: T::SHAPE,
+    })
+}
+
+/// Looks through a `FacetValue` to the value it holds.
+unsafe fn unwrap_value<'v>(
+    ptr: PtrConst<'v>,
+    shape: &'static Shape<'static>,
+) -> (PtrConst<'v>, &'static Shape<'static>) {
+    if shape.is_shape(FacetValue::SHAPE) {
+        let value: &FacetValue = unsafe { ptr.get() };
+        (value.as_ptr(), value.shape())
+    } else {
+        (ptr, shape)
+    }
+}
+
+unsafe fn unwrap_value_mut<'v>(
+    ptr: PtrMut<'v>,
+    shape: &'static Shape<'static>,
+) -> (PtrMut<'v>, &'static Shape<'static>) {
+    if shape.is_shape(FacetValue::SHAPE) {
+        let value: &mut FacetValue = unsafe { ptr.as_mut() };
+        let shape = value.shape();
+        (value.as_mut_ptr(), shape)
+    } else {
+        (ptr, shape)
+    }
+}
+
+unsafe fn child<'v>(
+    ptr: PtrConst<'v>,
+    shape: &'static Shape<'static>,
+    segment: &str,
+) -> Option<(PtrConst<'v>, &'static Shape<'static>)> {
+    let (ptr, shape) = unsafe { unwrap_value(ptr, shape) };
+
+    if shape.is_shape(NestedMap::SHAPE) {
+        let map: &NestedMap = unsafe { ptr.get() };
+        return Some((PtrConst::new(map.get(segment)?), FacetValue::SHAPE));
+    }
+
+    let field = struct_field(shape, segment)?;
+    Some((unsafe { ptr.field(field.offset) }, field.shape()))
+}
+
+/// If `create` is set, missing entries of nested maps are created as empty
+/// nested maps.
+unsafe fn child_mut<'v>(
+    ptr: PtrMut<'v>,
+    shape: &'static Shape<'static>,
+    segment: &str,
+    create: bool,
+) -> Option<(PtrMut<'v>, &'static Shape<'static>)> {
+    let (ptr, shape) = unsafe { unwrap_value_mut(ptr, shape) };
+
+    if shape.is_shape(NestedMap::SHAPE) {
+        let map: &mut NestedMap = unsafe { ptr.as_mut() };
+        let value = if create {
+            map.entry(segment.to_string())
+                .or_insert_with(|| FacetValue::new(NestedMap::new()))
+        } else {
+            map.get_mut(segment)?
+        };
+        return Some((PtrMut::new(value), FacetValue::SHAPE));
+    }
+
+    let field = struct_field(shape, segment)?;
+    Some((unsafe { field_mut(ptr, field.offset) }, field.shape()))
+}
+
+unsafe fn set_child<T: Facet<'static>>(
+    ptr: PtrMut<'_>,
+    shape: &'static Shape<'static>,
+    segment: &str,
+    value: T,
+) -> Result<(), T> {
+    let (ptr, shape) = unsafe { unwrap_value_mut(ptr, shape) };
+
+    if shape.is_shape(NestedMap::SHAPE) {
+        let map: &mut NestedMap = unsafe { ptr.as_mut() };
+        map.insert(segment.to_string(), FacetValue::new(value));
+        return Ok(());
+    }
+
+    let Some(field) = struct_field(shape, segment) else {
+        return Err(value);
+    };
+    let field_ptr = unsafe { field_mut(ptr, field.offset) };
+    if field.shape().is_shape(T::SHAPE) {
+        *unsafe { field_ptr.as_mut::<T>() } = value;
+        Ok(())
+    } else if field.shape().is_shape(FacetValue::SHAPE) {
+        *unsafe { field_ptr.as_mut::<FacetValue>() } = FacetValue::new(value);
+        Ok(())
+    } else {
+        Err(value)
+    }
+}
+
+unsafe fn field_mut(ptr: PtrMut<'_>, offset: usize) -> PtrMut<'_> {
+    PtrMut::new(unsafe { ptr.as_mut_byte_ptr().add(offset) })
+}
+
+fn struct_field(
+    shape: &'static Shape<'static>,
+    name: &str,
+) -> Option<&'static facet::Field<'static>> {
+    let Type::User(UserType::Struct(struct_type)) = shape.ty else {
+        return None;
+    };
+    struct_type.fields.iter().find(|field| field.name == name)
+}
+
+unsafe fn cast<'v, T: Facet<'static>>(
+    ptr: PtrConst<'v>,
+    shape: &'static Shape<'static>,
+) -> Option<&'v T> {
+    let (ptr, shape) = if shape.is_shape(T::SHAPE) {
+        (ptr, shape)
+    } else {
+        unsafe { unwrap_value(ptr, shape) }
+    };
+    shape.is_shape(T::SHAPE).then(|| unsafe { ptr.get() })
+}
+
+unsafe fn cast_mut<'v, T: Facet<'static>>(
+    ptr: PtrMut<'v>,
+    shape: &'static Shape<'static>,
+) -> Option<&'v mut T> {
+    let (ptr, shape) = if shape.is_shape(T::SHAPE) {
+        (ptr, shape)
+    } else {
+        unsafe { unwrap_value_mut(ptr, shape) }
+    };
+    shape.is_shape(T::SHAPE).then(|| unsafe { ptr.as_mut() })
+}
@@ -0,0 +1,166 @@
+use std::borrow::Borrow;
+use std::hash::BuildHasher;
+
+use facet::{Facet, PtrConst, PtrMut, Shape};
+
+use crate::erased::Erased;
+use crate::erased_hashmap::{ErasedHashMap, ErasedKey, ErasedKeyRef, ErasedValue};
+use crate::storage::{DefaultStorage, StorageBackend};
+
+/// A map with a statically typed key whose values all have one shape that
+/// is only known at runtime, e.g. a registry filled by loaded plugins.
+/// Typed accessors check `V`'s shape against the map's value shape.
+pub struct FacetDynValueMap<
+    'a,
+    K: Facet<'a>,
+    S = hashbrown::DefaultHashBuilder,
+    B: StorageBackend = DefaultStorage,
+> {
+    hash_map: ErasedHashMap<S, B>,
+    value_shape: &'static Shape<'static>,
+    _marker: std::marker::PhantomData<(K, &'a ())>,
+}
+
+impl<'a, K, S, B> Drop for FacetDynValueMap<'a, K, S, B>
+where
+    K: Facet<'a>,
+    B: StorageBackend,
+{
+    fn drop(&mut self) {
+        unsafe {
+            ErasedHashMap::drop_keys_and_values(&mut self.hash_map, K::SHAPE, self.value_shape);
+        }
+    }
+}
+
+impl<'a, K, S, B> FacetDynValueMap<'a, K, S, B>
+where
+    K: Facet<'a>,
+    B: StorageBackend,
+{
+    pub fn new(value_shape: &'static Shape<'static>) -> Self
+    where
+        S: Default,
+        B: Default,
+    {
+        Self::with_hasher_and_storage(value_shape, S::default(), B::default())
+    }
+
+    pub fn with_hasher_and_storage(
+        value_shape: &'static Shape<'static>,
+        hash_builder: S,
+        storage: B,
+    ) -> Self {
+        Self {
+            hash_map: ErasedHashMap::with_hasher_and_storage(hash_builder, storage),
+            value_shape,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    pub fn value_shape(&self) -> &'static Shape<'static> {
+        self.value_shape
+    }
+
+    /// Inserts `value` if `V` has the map's value shape, returning the old
+    /// value. Otherwise `value` is handed back.
+    pub fn insert<V: Facet<'static>>(&mut self, key: K, value: V) -> Result<Option<V>, V>
+    where
+        S: BuildHasher,
+    {
+        if !V::SHAPE.is_shape(self.value_shape) {
+            return Err(value);
+        }
+        let erased_value = ErasedValue(Erased::new(value, self.hash_map.storage()));
+        let old_erased_value = unsafe { self.insert_erased_value(key, erased_value) };
+        Ok(old_erased_value
+            .map(|old_value| unsafe { old_value.0.into_typed(self.hash_map.storage()) }))
+    }
+
+    /// Moves the value at `value` into the map, dropping the value it
+    /// replaces. Returns whether there was one.
+    ///
+    /// # Safety
+    /// `value` must point to an initialized value of the map's value shape,
+    /// which must not be used or dropped afterwards
+    pub unsafe fn insert_erased(&mut self, key: K, value: PtrMut<'_>) -> bool
+    where
+        S: BuildHasher,
+    {
+        let size = self.value_shape.layout.sized_layout().unwrap().size();
+        let erased_value = unsafe {
+            ErasedValue(Erased::from_init(
+                self.value_shape,
+                self.hash_map.storage(),
+                |dst| {
+                    std::ptr::copy_nonoverlapping(value.as_byte_ptr(), dst.as_mut_byte_ptr(), size);
+                },
+            ))
+        };
+
+        match unsafe { self.insert_erased_value(key, erased_value) } {
+            Some(mut old_value) => {
+                if let Some(drop_value) = Erased::drop_fn(self.value_shape, self.hash_map.storage())
+                {
+                    drop_value(&mut old_value.0);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    unsafe fn insert_erased_value(&mut self, key: K, value: ErasedValue) -> Option<ErasedValue>
+    where
+        S: BuildHasher,
+    {
+        let erased_key = ErasedKey(Erased::new(key, self.hash_map.storage()));
+        unsafe { self.hash_map.insert(erased_key, K::SHAPE, value) }
+    }
+
+    /// Points to the value for `key`, which has the map's value shape.
+    pub fn get_ptr<Q: Borrow<K>>(&self, key: &Q) -> Option<PtrConst<'_>>
+    where
+        S: BuildHasher,
+    {
+        let key_ref = ErasedKeyRef(PtrConst::new(key.borrow()));
+        let value_storage = self.hash_map.storage().storage_for(self.value_shape);
+        unsafe {
+            self.hash_map
+                .get(key_ref, K::SHAPE)
+                .map(|value| value.as_ptr(value_storage))
+        }
+    }
+
+    /// Returns the value for `key` if `V` has the map's value shape.
+    pub fn get<V: Facet<'static>, Q: Borrow<K>>(&self, key: &Q) -> Option<&V>
+    where
+        S: BuildHasher,
+    {
+        if !V::SHAPE.is_shape(self.value_shape) {
+            return None;
+        }
+        self.get_ptr(key).map(|ptr| unsafe { ptr.get() })
+    }
+
+    /// Removes and drops the entry for `key`, returning whether there was one.
+    pub fn remove<Q: Borrow<K>>(&mut self, key: &Q) -> bool
+    where
+        S: BuildHasher,
+    {
+        let key_ref = ErasedKeyRef(PtrConst::new(key.borrow()));
+        let Some((mut key, mut value)) = (unsafe { self.hash_map.remove(key_ref, K::SHAPE) })
+        else {
+            return false;
+        };
+
+        let storage = self.hash_map.storage();
+        if let Some(drop_key) = Erased::drop_fn(K::SHAPE, storage) {
+            drop_key(&mut key.0);
+        }
+        if let Some(drop_value) = Erased::drop_fn(self.value_shape, storage) {
+            drop_value(&mut value.0);
+        }
+        true
+    }
+}
@@ -0,0 +1,241 @@
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+
+use facet::Facet;
+
+use crate::erased::Erased;
+use crate::erased_hashmap::{ErasedKey, ErasedOccupiedEntry, ErasedVacantEntry, ErasedValue};
+use crate::storage::{DefaultStorage, StorageBackend};
+
+/// One entry of a [`crate::FacetHashMap`], from
+/// [`crate::FacetHashMap::entry`]. The key has already been looked up, so
+/// inserting or changing the entry doesn't hash it again.
+pub enum Entry<'m, 'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend = DefaultStorage> {
+    Occupied(OccupiedEntry<'m, 'a, K, V, B>),
+    Vacant(VacantEntry<'m, 'a, K, V, B>),
+}
+
+pub struct OccupiedEntry<'m, 'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend = DefaultStorage> {
+    pub(crate) entry: ErasedOccupiedEntry<'m, B>,
+    pub(crate) _marker: PhantomData<(K, V, &'a ())>,
+}
+
+pub struct VacantEntry<'m, 'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend = DefaultStorage> {
+    pub(crate) key: K,
+    pub(crate) entry: ErasedVacantEntry<'m, B>,
+    pub(crate) _marker: PhantomData<(V, &'a ())>,
+}
+
+/// An entry looked up by a borrowed form of the key, from
+/// [`crate::FacetHashMap::entry_ref`]. The owned key is only built from the
+/// borrowed one when inserting into a vacant entry.
+pub enum EntryRef<'m, 'q, 'a, K, Q, V, B = DefaultStorage>
+where
+    K: Facet<'a>,
+    Q: ?Sized,
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    Occupied(OccupiedEntry<'m, 'a, K, V, B>),
+    Vacant(VacantEntryRef<'m, 'q, 'a, K, Q, V, B>),
+}
+
+pub struct VacantEntryRef<'m, 'q, 'a, K, Q, V, B = DefaultStorage>
+where
+    K: Facet<'a>,
+    Q: ?Sized,
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    pub(crate) key: &'q Q,
+    pub(crate) entry: ErasedVacantEntry<'m, B>,
+    pub(crate) _marker: PhantomData<(K, V, &'a ())>,
+}
+
+impl<'m, 'a, K, V, B> Entry<'m, 'a, K, V, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'m mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with(self, default: impl FnOnce() -> V) -> &'m mut V {
+        self.or_insert_with_key(|_| default())
+    }
+
+    pub fn or_insert_with_key(self, default: impl FnOnce(&K) -> V) -> &'m mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    pub fn or_default(self) -> &'m mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` with the value if the entry is occupied.
+    pub fn and_modify(mut self, f: impl FnOnce(&mut V)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'m, 'a, K, V, B> OccupiedEntry<'m, 'a, K, V, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    /// The key stored in the map.
+    pub fn key(&self) -> &K {
+        let key_storage = self.entry.storage().storage_for(K::SHAPE);
+        unsafe { self.entry.key().as_ptr(key_storage).get() }
+    }
+
+    pub fn get(&self) -> &V {
+        let value_storage = self.entry.storage().storage_for(V::SHAPE);
+        unsafe { self.entry.value().as_ptr(value_storage).get() }
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        let value_storage = self.entry.storage().storage_for(V::SHAPE);
+        unsafe { self.entry.value_mut().as_mut_ptr(value_storage).as_mut() }
+    }
+
+    /// Like `get_mut`, but borrows from the map rather than the entry.
+    pub fn into_mut(self) -> &'m mut V {
+        let value_storage = self.entry.storage().storage_for(V::SHAPE);
+        unsafe {
+            self.entry
+                .into_value_mut()
+                .as_mut_ptr(value_storage)
+                .as_mut()
+        }
+    }
+
+    /// Replaces the value, returning the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    pub fn remove_entry(self) -> (K, V) {
+        let storage = self.entry.storage();
+        let (key, value) = self.entry.remove();
+        unsafe { (key.0.into_typed(storage), value.0.into_typed(storage)) }
+    }
+}
+
+impl<'m, 'a, K, V, B> VacantEntry<'m, 'a, K, V, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    /// The key the entry was looked up with.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> &'m mut V {
+        let storage = self.entry.storage();
+        let key = ErasedKey(Erased::new(self.key, storage));
+        let value = ErasedValue(Erased::new(value, storage));
+        let value_storage = storage.storage_for(V::SHAPE);
+        unsafe {
+            self.entry
+                .insert(key, value)
+                .as_mut_ptr(value_storage)
+                .as_mut()
+        }
+    }
+}
+
+impl<'m, 'q, 'a, K, Q, V, B> EntryRef<'m, 'q, 'a, K, Q, V, B>
+where
+    K: Facet<'a> + Borrow<Q> + From<&'q Q>,
+    Q: ?Sized,
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    pub fn key(&self) -> &Q {
+        match self {
+            EntryRef::Occupied(entry) => entry.key().borrow(),
+            EntryRef::Vacant(entry) => entry.key,
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'m mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with(self, default: impl FnOnce() -> V) -> &'m mut V {
+        match self {
+            EntryRef::Occupied(entry) => entry.into_mut(),
+            EntryRef::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn or_default(self) -> &'m mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` with the value if the entry is occupied.
+    pub fn and_modify(mut self, f: impl FnOnce(&mut V)) -> Self {
+        if let EntryRef::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'m, 'q, 'a, K, Q, V, B> VacantEntryRef<'m, 'q, 'a, K, Q, V, B>
+where
+    K: Facet<'a> + From<&'q Q>,
+    Q: ?Sized,
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    pub fn key(&self) -> &'q Q {
+        self.key
+    }
+
+    /// Builds the owned key and inserts it with `value`.
+    pub fn insert(self, value: V) -> &'m mut V {
+        VacantEntry {
+            key: K::from(self.key),
+            entry: self.entry,
+            _marker: PhantomData,
+        }
+        .insert(value)
+    }
+}
@@ -0,0 +1,92 @@
+use std::borrow::Borrow;
+use std::hash::BuildHasher;
+
+use facet::Facet;
+
+use crate::facet_hashmap::FacetHashMap;
+use crate::storage::{DefaultStorage, StorageBackend};
+
+/// A value along with the epoch it was last written or refreshed in.
+#[derive(Facet, Debug)]
+pub struct EpochEntry<V> {
+    pub value: V,
+    pub epoch: u64,
+}
+
+/// A map whose entries are tagged with a caller-supplied epoch, so that
+/// after rebuilding everything in a new epoch, the entries that weren't
+/// refreshed can be evicted with [`EpochFacetMap::remove_older_than`].
+pub struct EpochFacetMap<
+    'a,
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S = hashbrown::DefaultHashBuilder,
+    B: StorageBackend = DefaultStorage,
+> {
+    entries: FacetHashMap<'a, K, EpochEntry<V>, S, B>,
+}
+
+impl<'a, K, V, S, B> Default for EpochFacetMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S: Default,
+    B: StorageBackend + Default,
+{
+    fn default() -> Self {
+        Self {
+            entries: FacetHashMap::default(),
+        }
+    }
+}
+
+impl<'a, K, V, S, B> EpochFacetMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S: BuildHasher,
+    B: StorageBackend,
+{
+    /// Sets `key` to `value`, tagged with `epoch`.
+    pub fn insert(&mut self, key: K, value: V, epoch: u64) -> Option<V> {
+        self.entries
+            .insert(key, EpochEntry { value, epoch })
+            .map(|old| old.value)
+    }
+
+    /// Tags the entry for `key` with `epoch` without changing its value.
+    /// Returns false if there is no entry.
+    pub fn refresh<Q: Borrow<K>>(&mut self, key: &Q, epoch: u64) -> bool {
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.epoch = epoch;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get<Q: Borrow<K>>(&self, key: &Q) -> Option<&V> {
+        self.entries.get(key).map(|entry| &entry.value)
+    }
+
+    pub fn epoch<Q: Borrow<K>>(&self, key: &Q) -> Option<u64> {
+        self.entries.get(key).map(|entry| entry.epoch)
+    }
+
+    pub fn remove<Q: Borrow<K>>(&mut self, key: &Q) -> Option<V> {
+        self.entries.remove(key).map(|entry| entry.value)
+    }
+
+    /// Drops every entry last written or refreshed before `epoch`, returning
+    /// how many there were.
+    pub fn remove_older_than(&mut self, epoch: u64) -> usize {
+        let mut removed = 0;
+        self.entries.retain(|_, entry| {
+            let keep = entry.epoch >= epoch;
+            removed += usize::from(!keep);
+            keep
+        });
+        removed
+    }
+}
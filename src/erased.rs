@@ -2,6 +2,8 @@ use std::mem::MaybeUninit;
 
 use facet::{Facet, PtrConst, PtrMut, PtrUninit, Shape};
 
+use crate::storage::StorageBackend;
+
 type InlineStorage = usize;
 
 pub union ErasedUninit {
@@ -11,16 +13,16 @@ pub union ErasedUninit {
 }
 
 impl ErasedUninit {
-    fn as_ptr<'a>(&mut self, shape: &Shape) -> PtrUninit<'_> {
-        match ErasedStorage::for_shape(shape) {
+    fn as_ptr(&mut self, storage: ErasedStorage) -> PtrUninit<'_> {
+        match storage {
             ErasedStorage::Inline => PtrUninit::new(unsafe { self.inline.as_mut_ptr() }),
             ErasedStorage::Boxed => unsafe { self.boxed_ptr },
         }
     }
 
     /// Safety: Assumes that it is initialized
-    unsafe fn as_const_ptr_assume_init(&self, shape: &Shape) -> PtrConst<'_> {
-        match ErasedStorage::for_shape(shape) {
+    unsafe fn as_const_ptr_assume_init(&self, storage: ErasedStorage) -> PtrConst<'_> {
+        match storage {
             ErasedStorage::Inline => PtrConst::new(unsafe { self.inline.as_ptr() }),
             ErasedStorage::Boxed => unsafe { self.boxed_ptr.assume_init().as_const() },
         }
@@ -35,13 +37,18 @@ impl ErasedUninit {
 pub struct Erased(ErasedUninit);
 
 impl Erased {
-    pub fn uninit(shape: &Shape) -> ErasedUninit {
-        match ErasedStorage::for_shape(shape) {
-            ErasedStorage::Inline => ErasedUninit {
-                inline: MaybeUninit::uninit(),
-            },
+    pub fn uninit<B: StorageBackend>(shape: &Shape, backend: &B) -> ErasedUninit {
+        match backend.storage_for(shape) {
+            ErasedStorage::Inline => {
+                if needs_drop(shape) {
+                    backend.inline_created();
+                }
+                ErasedUninit {
+                    inline: MaybeUninit::uninit(),
+                }
+            }
             ErasedStorage::Boxed => {
-                let ptr = unsafe { std::alloc::alloc(shape.layout.sized_layout().unwrap()) };
+                let ptr = unsafe { backend.allocate(shape.layout.sized_layout().unwrap()) };
                 ErasedUninit {
                     boxed_ptr: PtrUninit::new(ptr),
                 }
@@ -49,74 +56,188 @@ impl Erased {
         }
     }
 
-    pub fn new<'a, T: 'a>(value: T) -> Self
-    where
-        T: Facet<'a>,
-    {
-        let mut uninit = Self::uninit(T::SHAPE);
-
+    pub fn new<'a, T: Facet<'a>, B: StorageBackend>(value: T, backend: &B) -> Self {
         unsafe {
-            {
-                let ptr = uninit.as_ptr(T::SHAPE);
+            Self::from_init(T::SHAPE, backend, |ptr| {
                 ptr.put(value);
+            })
+        }
+    }
+
+    /// Safety: `init` must initialize a value of `shape` at the pointer it's given
+    pub unsafe fn from_init<B: StorageBackend>(
+        shape: &Shape,
+        backend: &B,
+        init: impl FnOnce(PtrUninit<'_>),
+    ) -> Self {
+        let result = unsafe {
+            Self::try_from_init(shape, backend, |ptr| {
+                init(ptr);
+                Ok::<(), std::convert::Infallible>(())
+            })
+        };
+        match result {
+            Ok(erased) => erased,
+        }
+    }
+
+    /// Like `from_init`, but `init` may fail, in which case the boxed
+    /// allocation is freed again.
+    /// Safety: if `init` returns `Ok`, it must have initialized a value of
+    ///         `shape` at the pointer it's given
+    pub unsafe fn try_from_init<B: StorageBackend, E>(
+        shape: &Shape,
+        backend: &B,
+        init: impl FnOnce(PtrUninit<'_>) -> Result<(), E>,
+    ) -> Result<Self, E> {
+        let storage = backend.storage_for(shape);
+        let mut uninit = Self::uninit(shape, backend);
+
+        match init(uninit.as_ptr(storage)) {
+            Ok(()) => Ok(unsafe { uninit.assume_init() }),
+            Err(error) => {
+                unsafe {
+                    release_slot(
+                        backend,
+                        storage,
+                        uninit.as_ptr(storage).as_mut_byte_ptr(),
+                        shape,
+                    )
+                };
+                Err(error)
             }
-            uninit.assume_init()
         }
     }
 
-    /// returns a function to drop an erased value with `shape`
-    pub fn drop_fn<'a>(shape: &Shape) -> Option<impl Fn(*mut Erased)> {
+    /// returns a function to drop an erased value with `shape` that was
+    /// allocated by `backend`
+    pub fn drop_fn<'b, B: StorageBackend>(
+        shape: &Shape,
+        backend: &'b B,
+    ) -> Option<impl Fn(*mut Erased) + 'b> {
         let drop_in_place = (shape.vtable.drop_in_place)();
         let layout = shape.layout.sized_layout().unwrap();
 
-        match (ErasedStorage::for_shape(shape), drop_in_place) {
+        match (backend.storage_for(shape), drop_in_place) {
             (ErasedStorage::Inline, None) => None,
             (storage, drop_in_place) => Some(
                 #[inline(always)]
                 move |erased_ptr: *mut Erased| unsafe {
+                    let ptr = (*erased_ptr).as_mut_ptr(storage);
+                    if let Some(drop_in_place) = drop_in_place {
+                        drop_in_place(ptr);
+                    }
                     match storage {
-                        ErasedStorage::Inline => {
-                            if let Some(drop_in_place) = drop_in_place {
-                                drop_in_place(PtrMut::new(&raw mut (*erased_ptr).0.inline));
-                            }
-                        }
-                        ErasedStorage::Boxed => {
-                            let ptr = (*erased_ptr).0.boxed_ptr;
-                            if let Some(drop_in_place) = drop_in_place {
-                                drop_in_place(ptr.assume_init());
-                            }
-                            std::alloc::dealloc(ptr.as_mut_byte_ptr(), layout)
-                        }
+                        ErasedStorage::Boxed => backend.deallocate(ptr.as_mut_byte_ptr(), layout),
+                        ErasedStorage::Inline => backend.inline_released(),
                     }
                 },
             ),
         }
     }
 
-    /// Safety: must be correct shape for T
-    pub unsafe fn as_ptr<'a>(&'a self, shape: &Shape) -> PtrConst<'a> {
-        unsafe { self.0.as_const_ptr_assume_init(shape) }
+    /// Moves the value from the storage of `from` into the storage of `to`,
+    /// freeing its old allocation if it had one.
+    /// Safety: the value must have `shape` and have been created with `from`
+    pub unsafe fn move_to<B1: StorageBackend, B2: StorageBackend>(
+        mut self,
+        shape: &Shape,
+        from: &B1,
+        to: &B2,
+    ) -> Self {
+        let from_storage = from.storage_for(shape);
+        let layout = shape.layout.sized_layout().unwrap();
+        unsafe {
+            let src = self.as_mut_ptr(from_storage);
+            let moved = Self::from_init(shape, to, |dst| {
+                std::ptr::copy_nonoverlapping(
+                    src.as_byte_ptr(),
+                    dst.as_mut_byte_ptr(),
+                    layout.size(),
+                );
+            });
+            release_slot(from, from_storage, src.as_mut_byte_ptr(), shape);
+            moved
+        }
+    }
+
+    /// Frees the value's allocation, if it has one, without dropping the
+    /// value, which must have been moved out already.
+    /// Safety: the value must have `shape` and have been created with `backend`
+    pub unsafe fn forget<B: StorageBackend>(mut self, shape: &Shape, backend: &B) {
+        let storage = backend.storage_for(shape);
+        unsafe {
+            release_slot(
+                backend,
+                storage,
+                self.as_mut_ptr(storage).as_mut_byte_ptr(),
+                shape,
+            )
+        }
+    }
+
+    /// Safety: `storage` must be the storage this value was created with
+    pub unsafe fn as_ptr(&self, storage: ErasedStorage) -> PtrConst<'_> {
+        unsafe { self.0.as_const_ptr_assume_init(storage) }
+    }
+
+    /// Safety: `storage` must be the storage this value was created with
+    pub unsafe fn as_mut_ptr(&mut self, storage: ErasedStorage) -> PtrMut<'_> {
+        unsafe { self.0.as_ptr(storage).assume_init() }
     }
 
-    /// Safety: must be correct shape for T
-    pub unsafe fn as_mut_ptr<'a>(&'a mut self, shape: &Shape) -> PtrMut<'a> {
-        unsafe { self.0.as_ptr(shape).assume_init() }
+    /// Moves the value out, freeing its boxed allocation if it has one.
+    /// Safety: T must be the correct type and `backend` must be the backend
+    ///         this value was created with
+    pub unsafe fn into_typed<'a, T: Facet<'a>, B: StorageBackend>(mut self, backend: &B) -> T {
+        let storage = backend.storage_for(T::SHAPE);
+        unsafe {
+            let ptr = self.as_mut_ptr(storage);
+            let value = ptr.read();
+            release_slot(backend, storage, ptr.as_mut_byte_ptr(), T::SHAPE);
+            value
+        }
     }
+}
 
-    /// Safety: T must be the correct type
-    pub unsafe fn into_typed<'a, T: Facet<'a>>(self) -> T {
-        unsafe { self.as_ptr(T::SHAPE).read() }
+/// Inline values are only reported to the backend if their shape has a drop
+/// fn, since leaking the others is harmless.
+fn needs_drop(shape: &Shape) -> bool {
+    (shape.vtable.drop_in_place)().is_some()
+}
+
+/// Gives back the slot of a value that has been dropped or moved out.
+/// Safety: `ptr` points to the slot of a value of `shape` created with
+///         `backend`, which is stored as `storage`
+unsafe fn release_slot<B: StorageBackend>(
+    backend: &B,
+    storage: ErasedStorage,
+    ptr: *mut u8,
+    shape: &Shape,
+) {
+    match storage {
+        ErasedStorage::Boxed => unsafe {
+            backend.deallocate(ptr, shape.layout.sized_layout().unwrap())
+        },
+        ErasedStorage::Inline => {
+            if needs_drop(shape) {
+                backend.inline_released();
+            }
+        }
     }
 }
 
-#[derive(Clone, Copy)]
-enum ErasedStorage {
+/// Where an erased value lives: inline in the pointer-sized slot, or in a
+/// separate allocation made by the map's [`StorageBackend`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErasedStorage {
     Inline,
     Boxed,
 }
 
 impl ErasedStorage {
-    fn for_shape(shape: &Shape) -> Self {
+    /// The default classification: inline if the shape fits in the slot.
+    pub fn for_shape(shape: &Shape) -> Self {
         match shape.layout {
             facet::ShapeLayout::Sized(layout)
                 if layout.size() <= std::mem::size_of::<InlineStorage>()
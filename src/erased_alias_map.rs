@@ -0,0 +1,244 @@
+use std::hash::BuildHasher;
+
+use facet::{PtrConst, Shape};
+use hashbrown::HashTable;
+
+use crate::erased::{Erased, ErasedStorage};
+use crate::erased_hashmap::{ErasedKey, ErasedKeyRef, ErasedValue, KeyVTable, make_hash};
+use crate::storage::{DefaultStorage, StorageBackend};
+
+/// A value together with every key it can be reached by. The number of keys
+/// is the value's reference count.
+struct AliasGroup {
+    keys: Vec<ErasedKey>,
+    value: ErasedValue,
+}
+
+/// Points at one key of one group; the keys themselves are only stored in
+/// the groups.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct AliasRef {
+    group: usize,
+    alias: usize,
+}
+
+#[derive(Default)]
+pub struct ErasedAliasMap<S, B = DefaultStorage> {
+    hash_table: HashTable<AliasRef>,
+    groups: Vec<Option<AliasGroup>>,
+    /// indices of `None` slots in `groups`
+    free_groups: Vec<usize>,
+    hash_builder: S,
+    storage: B,
+}
+
+impl<S, B: StorageBackend> ErasedAliasMap<S, B> {
+    pub fn storage(&self) -> &B {
+        &self.storage
+    }
+
+    fn find(&self, key_ref: PtrConst<'_>, key_shape: &Shape) -> Option<AliasRef>
+    where
+        S: BuildHasher,
+    {
+        let key_storage = self.storage.storage_for(key_shape);
+        let hash = unsafe { make_hash(&self.hash_builder, key_ref, key_shape) };
+        let eq = unsafe { make_eq(&self.groups, key_ref, key_shape, key_storage) };
+
+        self.hash_table.find(hash, eq).copied()
+    }
+
+    /// Inserts `key` as a new group, or replaces the value shared by `key`'s
+    /// group if it is already present. In that case `key` is dropped.
+    /// Safety: `key` must have `key_shape`
+    #[inline(never)]
+    pub unsafe fn insert(
+        &mut self,
+        key: ErasedKey,
+        key_shape: &Shape,
+        value: ErasedValue,
+    ) -> Option<ErasedValue>
+    where
+        S: BuildHasher,
+    {
+        let key_storage = self.storage.storage_for(key_shape);
+        let key_ref = unsafe { key.as_ptr(key_storage) };
+
+        if let Some(alias_ref) = self.find(key_ref, key_shape) {
+            unsafe { self.drop_key(key, key_shape) };
+            let group = self.groups[alias_ref.group].as_mut().unwrap();
+            return Some(std::mem::replace(&mut group.value, value));
+        }
+
+        let group = AliasGroup {
+            keys: Vec::new(),
+            value,
+        };
+        let group_index = match self.free_groups.pop() {
+            Some(group_index) => {
+                self.groups[group_index] = Some(group);
+                group_index
+            }
+            None => {
+                self.groups.push(Some(group));
+                self.groups.len() - 1
+            }
+        };
+        unsafe { self.push_alias(group_index, key, key_shape) };
+        None
+    }
+
+    /// Makes `alias` another key for the value `key_ref` is a key for.
+    /// Returns `alias` back if `key_ref` is missing or `alias` is already
+    /// present.
+    /// Safety: `key_ref` and `alias` must have `key_shape`
+    #[inline(never)]
+    pub unsafe fn add_alias(
+        &mut self,
+        key_ref: ErasedKeyRef<'_>,
+        alias: ErasedKey,
+        key_shape: &Shape,
+    ) -> Result<(), ErasedKey>
+    where
+        S: BuildHasher,
+    {
+        let key_storage = self.storage.storage_for(key_shape);
+        let Some(alias_ref) = self.find(key_ref.0, key_shape) else {
+            return Err(alias);
+        };
+        if self
+            .find(unsafe { alias.as_ptr(key_storage) }, key_shape)
+            .is_some()
+        {
+            return Err(alias);
+        }
+
+        unsafe { self.push_alias(alias_ref.group, alias, key_shape) };
+        Ok(())
+    }
+
+    /// Safety: `key` must have `key_shape` and not already be in the map
+    unsafe fn push_alias(&mut self, group_index: usize, key: ErasedKey, key_shape: &Shape)
+    where
+        S: BuildHasher,
+    {
+        let key_storage = self.storage.storage_for(key_shape);
+        let hash = unsafe { make_hash(&self.hash_builder, key.as_ptr(key_storage), key_shape) };
+
+        let group = self.groups[group_index].as_mut().unwrap();
+        let alias_ref = AliasRef {
+            group: group_index,
+            alias: group.keys.len(),
+        };
+        group.keys.push(key);
+
+        let groups = &self.groups;
+        let hash_builder = &self.hash_builder;
+        self.hash_table
+            .insert_unique(hash, alias_ref, |alias_ref| unsafe {
+                let key = &groups[alias_ref.group].as_ref().unwrap().keys[alias_ref.alias];
+                make_hash(hash_builder, key.as_ptr(key_storage), key_shape)
+            });
+    }
+
+    #[inline(never)]
+    pub unsafe fn get<'a>(
+        &'a self,
+        key_ref: ErasedKeyRef<'_>,
+        key_shape: &Shape,
+    ) -> Option<&'a ErasedValue>
+    where
+        S: BuildHasher,
+    {
+        let alias_ref = self.find(key_ref.0, key_shape)?;
+        Some(&self.groups[alias_ref.group].as_ref().unwrap().value)
+    }
+
+    /// The number of keys that reach the same value as `key_ref`, or 0 if
+    /// `key_ref` isn't in the map.
+    pub unsafe fn alias_count(&self, key_ref: ErasedKeyRef<'_>, key_shape: &Shape) -> usize
+    where
+        S: BuildHasher,
+    {
+        self.find(key_ref.0, key_shape).map_or(0, |alias_ref| {
+            self.groups[alias_ref.group].as_ref().unwrap().keys.len()
+        })
+    }
+
+    /// Removes the value `key_ref` is a key for, along with all of its keys.
+    #[inline(never)]
+    pub unsafe fn remove(
+        &mut self,
+        key_ref: ErasedKeyRef<'_>,
+        key_shape: &Shape,
+    ) -> Option<ErasedValue>
+    where
+        S: BuildHasher,
+    {
+        let group_index = self.find(key_ref.0, key_shape)?.group;
+        let group = self.groups[group_index].take().unwrap();
+        self.free_groups.push(group_index);
+
+        let key_storage = self.storage.storage_for(key_shape);
+        for (alias, key) in group.keys.iter().enumerate() {
+            let hash = unsafe { make_hash(&self.hash_builder, key.as_ptr(key_storage), key_shape) };
+            let removed_ref = AliasRef {
+                group: group_index,
+                alias,
+            };
+            if let Ok(entry) = self
+                .hash_table
+                .find_entry(hash, |alias_ref| *alias_ref == removed_ref)
+            {
+                entry.remove();
+            }
+        }
+        for key in group.keys {
+            unsafe { self.drop_key(key, key_shape) };
+        }
+
+        Some(group.value)
+    }
+
+    unsafe fn drop_key(&self, mut key: ErasedKey, key_shape: &Shape) {
+        if let Some(drop_key) = Erased::drop_fn(key_shape, &self.storage) {
+            drop_key(&mut key.0);
+        }
+    }
+
+    /// Drops the keys and values in the map, which requires the shapes
+    /// and cannot be done in the Drop impl for this struct.
+    /// Safety: `this` is a valid pointer and `key_shape` and `value_shape` are the
+    ///         the correct shapes.
+    pub unsafe fn drop_keys_and_values(this: *mut Self, key_shape: &Shape, value_shape: &Shape) {
+        let storage = unsafe { &(*this).storage };
+        let drop_key = Erased::drop_fn(key_shape, storage);
+        let drop_value = Erased::drop_fn(value_shape, storage);
+
+        if drop_key.is_some() || drop_value.is_some() {
+            for group in unsafe { (*this).groups.iter_mut().flatten() } {
+                if let Some(drop_key) = &drop_key {
+                    for key in &mut group.keys {
+                        drop_key(&mut key.0);
+                    }
+                }
+                if let Some(drop_value) = &drop_value {
+                    drop_value(&mut group.value.0);
+                }
+            }
+        }
+    }
+}
+
+unsafe fn make_eq<'a>(
+    groups: &'a [Option<AliasGroup>],
+    key_ref: PtrConst<'a>,
+    key_shape: &'a Shape,
+    key_storage: ErasedStorage,
+) -> impl FnMut(&AliasRef) -> bool + 'a {
+    let key_vtable = KeyVTable::for_shape(key_shape);
+    move |alias_ref| unsafe {
+        let key = &groups[alias_ref.group].as_ref().unwrap().keys[alias_ref.alias];
+        key_vtable.eq(key_ref, key.as_ptr(key_storage))
+    }
+}
@@ -1,9 +1,12 @@
 use std::hash::{BuildHasher, Hasher};
 
-use facet::{HashFn, PtrConst, PtrMut, Shape};
+use facet::{
+    BorrowFn, CmpFn, Def, EnumRepr, HashFn, PartialEqFn, PtrConst, PtrMut, Shape, Type, UserType,
+};
 use hashbrown::HashTable;
 
-use crate::erased::Erased;
+use crate::erased::{Erased, ErasedStorage};
+use crate::storage::{DefaultStorage, StorageBackend};
 
 #[derive(Clone, Copy)]
 pub struct ErasedKeyRef<'a>(pub(crate) PtrConst<'a>);
@@ -45,15 +48,433 @@ impl std::ops::DerefMut for ErasedValue {
 struct HashTableEntry {
     pub key: ErasedKey,
     pub value: ErasedValue,
+    #[cfg(feature = "entry-timestamps")]
+    pub meta: EntryMeta,
+}
+
+impl HashTableEntry {
+    fn new(key: ErasedKey, value: ErasedValue) -> Self {
+        Self {
+            key,
+            value,
+            #[cfg(feature = "entry-timestamps")]
+            meta: EntryMeta::new(),
+        }
+    }
+
+    /// Hands out the value for changing it, which counts as an update.
+    fn value_mut(&mut self) -> &mut ErasedValue {
+        self.key_and_value_mut().1
+    }
+
+    fn key_and_value_mut(&mut self) -> (&ErasedKey, &mut ErasedValue) {
+        #[cfg(feature = "entry-timestamps")]
+        {
+            self.meta.updated_at = std::time::Instant::now();
+        }
+        (&self.key, &mut self.value)
+    }
+}
+
+/// When an entry was inserted and last changed. Replacing the value or
+/// getting a mutable reference to it counts as a change.
+#[cfg(feature = "entry-timestamps")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryMeta {
+    pub created_at: std::time::Instant,
+    pub updated_at: std::time::Instant,
+}
+
+#[cfg(feature = "entry-timestamps")]
+impl EntryMeta {
+    fn new() -> Self {
+        let now = std::time::Instant::now();
+        Self {
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// An entry found by [`ErasedHashMap::entry`].
+pub enum ErasedEntry<'m, B> {
+    Occupied(ErasedOccupiedEntry<'m, B>),
+    Vacant(ErasedVacantEntry<'m, B>),
+}
+
+pub struct ErasedOccupiedEntry<'m, B> {
+    slot: OccupiedSlot<'m>,
+    storage: &'m B,
+}
+
+enum OccupiedSlot<'m> {
+    Hashed(hashbrown::hash_table::OccupiedEntry<'m, HashTableEntry>),
+    Sorted {
+        entries: &'m mut Vec<HashTableEntry>,
+        index: usize,
+    },
+}
+
+impl<'m, B> ErasedOccupiedEntry<'m, B> {
+    pub fn storage(&self) -> &'m B {
+        self.storage
+    }
+
+    fn entry(&self) -> &HashTableEntry {
+        match &self.slot {
+            OccupiedSlot::Hashed(occupied_entry) => occupied_entry.get(),
+            OccupiedSlot::Sorted { entries, index } => &entries[*index],
+        }
+    }
+
+    pub fn key(&self) -> &ErasedKey {
+        &self.entry().key
+    }
+
+    pub fn value(&self) -> &ErasedValue {
+        &self.entry().value
+    }
+
+    pub fn value_mut(&mut self) -> &mut ErasedValue {
+        match &mut self.slot {
+            OccupiedSlot::Hashed(occupied_entry) => occupied_entry.get_mut().value_mut(),
+            OccupiedSlot::Sorted { entries, index } => entries[*index].value_mut(),
+        }
+    }
+
+    pub fn into_value_mut(self) -> &'m mut ErasedValue {
+        match self.slot {
+            OccupiedSlot::Hashed(occupied_entry) => occupied_entry.into_mut().value_mut(),
+            OccupiedSlot::Sorted { entries, index } => entries[index].value_mut(),
+        }
+    }
+
+    /// Removes the entry, handing back its erased key and value.
+    pub fn remove(self) -> (ErasedKey, ErasedValue) {
+        let hash_table_entry = match self.slot {
+            OccupiedSlot::Hashed(occupied_entry) => occupied_entry.remove().0,
+            OccupiedSlot::Sorted { entries, index } => entries.remove(index),
+        };
+        (hash_table_entry.key, hash_table_entry.value)
+    }
+}
+
+pub struct ErasedVacantEntry<'m, B> {
+    slot: VacantSlot<'m>,
+    storage: &'m B,
+}
+
+enum VacantSlot<'m> {
+    Hashed(hashbrown::hash_table::VacantEntry<'m, HashTableEntry>),
+    Sorted {
+        entries: &'m mut Vec<HashTableEntry>,
+        index: usize,
+    },
+    /// looked up by a borrowed key, which can't be ordered against the
+    /// keys, so the position is found when inserting
+    SortedUnplaced {
+        entries: &'m mut Vec<HashTableEntry>,
+        key_vtable: KeyVTable,
+        key_storage: ErasedStorage,
+    },
+}
+
+impl<'m, B> ErasedVacantEntry<'m, B> {
+    pub fn storage(&self) -> &'m B {
+        self.storage
+    }
+
+    /// `key` must be equal to the key the entry was looked up with.
+    pub fn insert(self, key: ErasedKey, value: ErasedValue) -> &'m mut ErasedValue {
+        let hash_table_entry = HashTableEntry::new(key, value);
+        match self.slot {
+            VacantSlot::Hashed(vacant_entry) => {
+                &mut vacant_entry.insert(hash_table_entry).into_mut().value
+            }
+            VacantSlot::Sorted { entries, index } => {
+                entries.insert(index, hash_table_entry);
+                &mut entries[index].value
+            }
+            VacantSlot::SortedUnplaced {
+                entries,
+                key_vtable,
+                key_storage,
+            } => {
+                let key_ref = unsafe { hash_table_entry.key.as_ptr(key_storage) };
+                let index = entries
+                    .binary_search_by(|entry| unsafe {
+                        key_vtable.cmp(entry.key.as_ptr(key_storage), key_ref)
+                    })
+                    .unwrap_or_else(|index| index);
+                entries.insert(index, hash_table_entry);
+                &mut entries[index].value
+            }
+        }
+    }
+}
+
+/// Iterates over the hashed entries, then the sorted ones.
+#[derive(Clone)]
+pub struct ErasedIter<'m> {
+    hashed: hashbrown::hash_table::Iter<'m, HashTableEntry>,
+    sorted: std::slice::Iter<'m, HashTableEntry>,
+}
+
+impl<'m> Iterator for ErasedIter<'m> {
+    type Item = (&'m ErasedKey, &'m ErasedValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.hashed.next().or_else(|| self.sorted.next())?;
+        Some((&entry.key, &entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.hashed.len() + self.sorted.len();
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for ErasedIter<'_> {}
+
+impl std::iter::FusedIterator for ErasedIter<'_> {}
+
+/// Like [`ErasedIter`], but hands out the values for changing them.
+pub struct ErasedIterMut<'m> {
+    hashed: hashbrown::hash_table::IterMut<'m, HashTableEntry>,
+    sorted: std::slice::IterMut<'m, HashTableEntry>,
+}
+
+impl<'m> Iterator for ErasedIterMut<'m> {
+    type Item = (&'m ErasedKey, &'m mut ErasedValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.hashed.next().or_else(|| self.sorted.next())?;
+        Some(entry.key_and_value_mut())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.hashed.len() + self.sorted.len();
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for ErasedIterMut<'_> {}
+
+impl std::iter::FusedIterator for ErasedIterMut<'_> {}
+
+/// Moves the entries out of an [`ErasedHashMap`], keeping its storage so
+/// the caller can move the keys and values out of it. Entries that are
+/// never taken are leaked unless the caller drops them.
+pub struct ErasedIntoIter<B> {
+    hashed: hashbrown::hash_table::IntoIter<HashTableEntry>,
+    sorted: std::vec::IntoIter<HashTableEntry>,
+    storage: B,
+}
+
+impl<B> ErasedIntoIter<B> {
+    pub fn storage(&self) -> &B {
+        &self.storage
+    }
+}
+
+impl<B> Iterator for ErasedIntoIter<B> {
+    type Item = (ErasedKey, ErasedValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.hashed.next().or_else(|| self.sorted.next())?;
+        Some((entry.key, entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.hashed.len() + self.sorted.len();
+        (len, Some(len))
+    }
+}
+
+impl<B> ExactSizeIterator for ErasedIntoIter<B> {}
+
+impl<B> std::iter::FusedIterator for ErasedIntoIter<B> {}
+
+/// The fns keys are hashed and compared with. A smart pointer shape like
+/// `Arc<T>` has none of its own, so its keys are compared by the pointee.
+#[derive(Clone, Copy)]
+pub(crate) struct KeyVTable {
+    pub hash: Option<HashFn>,
+    pub eq: Option<PartialEqFn>,
+    pub ord: Option<CmpFn>,
+    borrow: Option<BorrowFn>,
+    /// size of the tag at the start of enum keys, compared before calling
+    /// `eq` so keys of different variants don't go through the payload
+    tag_size: Option<usize>,
+}
+
+impl KeyVTable {
+    pub fn for_shape(key_shape: &Shape) -> Self {
+        let vtable = key_shape.vtable;
+        let own = Self {
+            hash: (vtable.hash)(),
+            eq: (vtable.partial_eq)(),
+            ord: (vtable.ord)(),
+            borrow: None,
+            tag_size: enum_tag_size(key_shape),
+        };
+        if own.eq.is_some() || own.ord.is_some() {
+            return own;
+        }
+
+        match key_shape.def {
+            Def::SmartPointer(smart_pointer) => {
+                match (smart_pointer.vtable.borrow_fn, smart_pointer.pointee()) {
+                    (Some(borrow), Some(pointee)) => Self {
+                        borrow: Some(borrow),
+                        ..Self::for_shape(pointee)
+                    },
+                    _ => own,
+                }
+            }
+            _ => own,
+        }
+    }
+
+    /// Points to the value the fns should be called with.
+    /// Safety: `key_ref` points to a key of the shape this was made for.
+    pub unsafe fn target<'k>(&self, key_ref: PtrConst<'k>) -> PtrConst<'k> {
+        match self.borrow {
+            Some(borrow) => unsafe { borrow(key_ref) },
+            None => key_ref,
+        }
+    }
+
+    /// Safety: both point to keys of the shape this was made for.
+    pub unsafe fn eq(&self, a: PtrConst<'_>, b: PtrConst<'_>) -> bool {
+        let (a, b) = unsafe { (self.target(a), self.target(b)) };
+        if let Some(tag_size) = self.tag_size {
+            let tag = |key: PtrConst<'_>| unsafe {
+                std::slice::from_raw_parts(key.as_byte_ptr(), tag_size)
+            };
+            if tag(a) != tag(b) {
+                return false;
+            }
+        }
+        unsafe { (self.eq.unwrap())(a, b) }
+    }
+
+    /// Safety: both point to keys of the shape this was made for.
+    pub unsafe fn cmp(&self, a: PtrConst<'_>, b: PtrConst<'_>) -> std::cmp::Ordering {
+        unsafe { (self.ord.unwrap())(self.target(a), self.target(b)) }
+    }
+}
+
+/// Derived enums have an explicit repr, and a primitive repr puts the tag
+/// first. Niche optimized enums have no tag to read. Comparing tags before
+/// `eq` assumes keys of different variants are never equal, which holds for
+/// a derived `PartialEq`.
+pub(crate) fn enum_tag_size(shape: &Shape) -> Option<usize> {
+    let Type::User(UserType::Enum(enum_type)) = shape.ty else {
+        return None;
+    };
+    match enum_type.enum_repr {
+        EnumRepr::U8 | EnumRepr::I8 => Some(1),
+        EnumRepr::U16 | EnumRepr::I16 => Some(2),
+        EnumRepr::U32 | EnumRepr::I32 => Some(4),
+        EnumRepr::U64 | EnumRepr::I64 => Some(8),
+        EnumRepr::USize | EnumRepr::ISize => Some(std::mem::size_of::<usize>()),
+        _ => None,
+    }
+}
+
+/// How entries are indexed, decided by the capabilities of the key shape.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum KeyIndex {
+    /// the key shape has `hash` and `partial_eq`: entries live in `hash_table`
+    Hashed,
+    /// the key shape only has `ord`: entries live in `sorted_entries`
+    Sorted,
+}
+
+impl KeyIndex {
+    fn for_shape(key_shape: &Shape) -> Self {
+        let key_vtable = KeyVTable::for_shape(key_shape);
+        if key_vtable.hash.is_some() && key_vtable.eq.is_some() {
+            Self::Hashed
+        } else if key_vtable.ord.is_some() {
+            Self::Sorted
+        } else {
+            panic!("key shape `{key_shape}` implements neither Hash + PartialEq nor Ord")
+        }
+    }
 }
 
 #[derive(Default)]
-pub struct ErasedHashMap<S> {
+pub struct ErasedHashMap<S, B = DefaultStorage> {
     hash_table: HashTable<HashTableEntry>,
+    /// entries ordered by the key shape's `ord`, used instead of `hash_table`
+    /// for key shapes that can't be hashed
+    sorted_entries: Vec<HashTableEntry>,
     hash_builder: S,
+    storage: B,
+    /// bumped by every change to the entries
+    generation: u64,
 }
 
-impl<S> ErasedHashMap<S> {
+impl<S, B: StorageBackend> ErasedHashMap<S, B> {
+    pub fn with_hasher_and_storage(hash_builder: S, storage: B) -> Self {
+        Self {
+            hash_table: HashTable::new(),
+            sorted_entries: Vec::new(),
+            hash_builder,
+            storage,
+            generation: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.hash_table.len() + self.sorted_entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.hash_table.capacity() + self.sorted_entries.capacity()
+    }
+
+    /// Makes room for `additional` more entries without resizing.
+    /// Safety: `key_shape` is the correct shape.
+    pub unsafe fn reserve(&mut self, additional: usize, key_shape: &Shape)
+    where
+        S: BuildHasher,
+    {
+        if KeyIndex::for_shape(key_shape) == KeyIndex::Sorted {
+            self.sorted_entries.reserve(additional);
+            return;
+        }
+        let key_storage = self.storage.storage_for(key_shape);
+        self.hash_table.reserve(additional, unsafe {
+            make_table_entry_hasher(&self.hash_builder, key_shape, key_storage)
+        });
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Only used to carry the generation over when the map is replaced, so
+    /// `generation` must not be lower than the current one.
+    pub fn set_generation(&mut self, generation: u64) {
+        debug_assert!(generation >= self.generation);
+        self.generation = generation;
+    }
+
+    pub fn storage(&self) -> &B {
+        &self.storage
+    }
+
+    pub fn storage_mut(&mut self) -> &mut B {
+        &mut self.storage
+    }
+
     #[inline(never)]
     pub unsafe fn insert(
         &mut self,
@@ -64,24 +485,111 @@ impl<S> ErasedHashMap<S> {
     where
         S: BuildHasher,
     {
-        let hash = unsafe { make_hash(&self.hash_builder, key.as_ptr(key_shape), key_shape) };
+        unsafe { self.insert_or_get(key, key_shape, value) }
+            .map(|(existing, value)| std::mem::replace(existing, value))
+    }
+
+    /// Inserts `value` if there's no entry for `key` yet. Otherwise `key` is
+    /// dropped and the existing value is returned along with `value`.
+    #[inline(never)]
+    pub unsafe fn insert_or_get(
+        &mut self,
+        key: ErasedKey,
+        key_shape: &Shape,
+        value: ErasedValue,
+    ) -> Option<(&mut ErasedValue, ErasedValue)>
+    where
+        S: BuildHasher,
+    {
+        let key_storage = self.storage.storage_for(key_shape);
+        let key_ref = unsafe { key.as_ptr(key_storage) };
+        // callers get a mutable reference to the existing value, so count
+        // that as a change too
+        self.generation += 1;
+
+        if KeyIndex::for_shape(key_shape) == KeyIndex::Sorted {
+            return match unsafe { self.sorted_position(key_ref, key_shape, key_storage) } {
+                Ok(index) => {
+                    unsafe { Self::drop_erased(&self.storage, key.0, key_shape) };
+                    Some((self.sorted_entries[index].value_mut(), value))
+                }
+                Err(index) => {
+                    self.sorted_entries
+                        .insert(index, HashTableEntry::new(key, value));
+                    None
+                }
+            };
+        }
+
+        let hash = unsafe { make_hash(&self.hash_builder, key_ref, key_shape) };
 
         match self.hash_table.entry(
             hash,
-            unsafe { make_eq(key.as_ptr(key_shape), key_shape) },
-            unsafe { make_table_entry_hasher(&self.hash_builder, key_shape) },
+            unsafe { make_eq(key_ref, key_shape, key_storage) },
+            unsafe { make_table_entry_hasher(&self.hash_builder, key_shape, key_storage) },
         ) {
             hashbrown::hash_table::Entry::Occupied(occupied_entry) => {
-                let hash_table_entry = occupied_entry.into_mut();
-                Some(std::mem::replace(&mut hash_table_entry.value, value))
+                unsafe { Self::drop_erased(&self.storage, key.0, key_shape) };
+                Some((occupied_entry.into_mut().value_mut(), value))
             }
             hashbrown::hash_table::Entry::Vacant(vacant_entry) => {
-                vacant_entry.insert(HashTableEntry { key, value });
+                vacant_entry.insert(HashTableEntry::new(key, value));
                 None
             }
         }
     }
 
+    /// Looks up `key_ref` once, for inserting into or changing the entry
+    /// afterwards without hashing again.
+    /// Safety: `key_shape` is the correct shape.
+    pub unsafe fn entry<'m>(
+        &'m mut self,
+        key_ref: ErasedKeyRef<'_>,
+        key_shape: &Shape,
+    ) -> ErasedEntry<'m, B>
+    where
+        S: BuildHasher,
+    {
+        let key_storage = self.storage.storage_for(key_shape);
+        self.generation += 1;
+
+        if KeyIndex::for_shape(key_shape) == KeyIndex::Sorted {
+            let position = unsafe { self.sorted_position(key_ref.0, key_shape, key_storage) };
+            let entries = &mut self.sorted_entries;
+            return match position {
+                Ok(index) => ErasedEntry::Occupied(ErasedOccupiedEntry {
+                    slot: OccupiedSlot::Sorted { entries, index },
+                    storage: &self.storage,
+                }),
+                Err(index) => ErasedEntry::Vacant(ErasedVacantEntry {
+                    slot: VacantSlot::Sorted { entries, index },
+                    storage: &self.storage,
+                }),
+            };
+        }
+
+        let hash = unsafe { make_hash(&self.hash_builder, key_ref.0, key_shape) };
+
+        match self.hash_table.entry(
+            hash,
+            unsafe { make_eq(key_ref.0, key_shape, key_storage) },
+            unsafe { make_table_entry_hasher(&self.hash_builder, key_shape, key_storage) },
+        ) {
+            hashbrown::hash_table::Entry::Occupied(occupied_entry) => {
+                ErasedEntry::Occupied(ErasedOccupiedEntry {
+                    slot: OccupiedSlot::Hashed(occupied_entry),
+                    storage: &self.storage,
+                })
+            }
+            hashbrown::hash_table::Entry::Vacant(vacant_entry) => {
+                ErasedEntry::Vacant(ErasedVacantEntry {
+                    slot: VacantSlot::Hashed(vacant_entry),
+                    storage: &self.storage,
+                })
+            }
+        }
+    }
+
     #[inline(never)]
     pub unsafe fn get<'a>(
         &'a self,
@@ -91,12 +599,399 @@ impl<S> ErasedHashMap<S> {
     where
         S: BuildHasher,
     {
+        unsafe { self.find_entry(key_ref, key_shape) }
+            .map(|hash_table_entry| &hash_table_entry.value)
+    }
+
+    /// Safety: `key_shape` is the correct shape.
+    pub unsafe fn contains_key(&self, key_ref: ErasedKeyRef<'_>, key_shape: &Shape) -> bool
+    where
+        S: BuildHasher,
+    {
+        unsafe { self.find_entry(key_ref, key_shape) }.is_some()
+    }
+
+    /// Safety: `key_shape` is the correct shape.
+    #[cfg(feature = "entry-timestamps")]
+    pub unsafe fn get_meta(&self, key_ref: ErasedKeyRef<'_>, key_shape: &Shape) -> Option<EntryMeta>
+    where
+        S: BuildHasher,
+    {
+        unsafe { self.find_entry(key_ref, key_shape) }.map(|hash_table_entry| hash_table_entry.meta)
+    }
+
+    unsafe fn find_entry<'a>(
+        &'a self,
+        key_ref: ErasedKeyRef<'_>,
+        key_shape: &Shape,
+    ) -> Option<&'a HashTableEntry>
+    where
+        S: BuildHasher,
+    {
+        let key_storage = self.storage.storage_for(key_shape);
+
+        if KeyIndex::for_shape(key_shape) == KeyIndex::Sorted {
+            let index = unsafe { self.sorted_position(key_ref.0, key_shape, key_storage) };
+            return index.ok().map(|index| &self.sorted_entries[index]);
+        }
+
+        let hash = unsafe { make_hash(&self.hash_builder, key_ref.0, key_shape) };
+        let eq = unsafe { make_eq(key_ref.0, key_shape, key_storage) };
+
+        self.hash_table.find(hash, eq)
+    }
+
+    #[inline(never)]
+    pub unsafe fn get_mut<'a>(
+        &'a mut self,
+        key_ref: ErasedKeyRef<'_>,
+        key_shape: &Shape,
+    ) -> Option<&'a mut ErasedValue>
+    where
+        S: BuildHasher,
+    {
+        let key_storage = self.storage.storage_for(key_shape);
+        self.generation += 1;
+
+        if KeyIndex::for_shape(key_shape) == KeyIndex::Sorted {
+            let index = unsafe { self.sorted_position(key_ref.0, key_shape, key_storage) };
+            return index
+                .ok()
+                .map(|index| self.sorted_entries[index].value_mut());
+        }
+
         let hash = unsafe { make_hash(&self.hash_builder, key_ref.0, key_shape) };
-        let eq = unsafe { make_eq(key_ref.0, key_shape) };
+        let eq = unsafe { make_eq(key_ref.0, key_shape, key_storage) };
+
+        let value = self.hash_table.find_mut(hash, eq);
+
+        value.map(HashTableEntry::value_mut)
+    }
+
+    /// Removes the entry for `key_ref`, handing back its erased key and value.
+    #[inline(never)]
+    pub unsafe fn remove(
+        &mut self,
+        key_ref: ErasedKeyRef<'_>,
+        key_shape: &Shape,
+    ) -> Option<(ErasedKey, ErasedValue)>
+    where
+        S: BuildHasher,
+    {
+        let key_storage = self.storage.storage_for(key_shape);
+
+        let hash_table_entry = if KeyIndex::for_shape(key_shape) == KeyIndex::Sorted {
+            let index = unsafe { self.sorted_position(key_ref.0, key_shape, key_storage) };
+            self.sorted_entries.remove(index.ok()?)
+        } else {
+            let hash = unsafe { make_hash(&self.hash_builder, key_ref.0, key_shape) };
+            let eq = unsafe { make_eq(key_ref.0, key_shape, key_storage) };
+            let occupied_entry = self.hash_table.find_entry(hash, eq).ok()?;
+            occupied_entry.remove().0
+        };
+        self.generation += 1;
+
+        Some((hash_table_entry.key, hash_table_entry.value))
+    }
+
+    /// Finds the value whose key satisfies `eq`, given a borrowed form of
+    /// the key that hashes like it. Sorted keys aren't hashed, so for them
+    /// every entry is checked.
+    /// Safety: `key_shape` is the correct shape.
+    pub unsafe fn find<Q: std::hash::Hash + ?Sized>(
+        &self,
+        borrowed_key: &Q,
+        key_shape: &Shape,
+        mut eq: impl FnMut(PtrConst<'_>) -> bool,
+    ) -> Option<&ErasedValue>
+    where
+        S: BuildHasher,
+    {
+        let key_storage = self.storage.storage_for(key_shape);
+
+        if KeyIndex::for_shape(key_shape) == KeyIndex::Sorted {
+            return self
+                .sorted_entries
+                .iter()
+                .find(|entry| eq(unsafe { entry.key.as_ptr(key_storage) }))
+                .map(|entry| &entry.value);
+        }
+
+        let hash = hash_typed(&self.hash_builder, borrowed_key);
+        self.hash_table
+            .find(hash, |entry| eq(unsafe { entry.key.as_ptr(key_storage) }))
+            .map(|entry| &entry.value)
+    }
+
+    /// Like `entry`, but looked up by a borrowed form of the key like `find`.
+    /// Safety: `key_shape` is the correct shape.
+    pub unsafe fn entry_borrowed<'m, Q: std::hash::Hash + ?Sized>(
+        &'m mut self,
+        borrowed_key: &Q,
+        key_shape: &Shape,
+        mut eq: impl FnMut(PtrConst<'_>) -> bool,
+    ) -> ErasedEntry<'m, B>
+    where
+        S: BuildHasher,
+    {
+        let key_storage = self.storage.storage_for(key_shape);
+        self.generation += 1;
+
+        if KeyIndex::for_shape(key_shape) == KeyIndex::Sorted {
+            let position = self
+                .sorted_entries
+                .iter()
+                .position(|entry| eq(unsafe { entry.key.as_ptr(key_storage) }));
+            let entries = &mut self.sorted_entries;
+            return match position {
+                Some(index) => ErasedEntry::Occupied(ErasedOccupiedEntry {
+                    slot: OccupiedSlot::Sorted { entries, index },
+                    storage: &self.storage,
+                }),
+                None => ErasedEntry::Vacant(ErasedVacantEntry {
+                    slot: VacantSlot::SortedUnplaced {
+                        entries,
+                        key_vtable: KeyVTable::for_shape(key_shape),
+                        key_storage,
+                    },
+                    storage: &self.storage,
+                }),
+            };
+        }
+
+        let hash = hash_typed(&self.hash_builder, borrowed_key);
+
+        match self.hash_table.entry(
+            hash,
+            |entry| eq(unsafe { entry.key.as_ptr(key_storage) }),
+            unsafe { make_table_entry_hasher(&self.hash_builder, key_shape, key_storage) },
+        ) {
+            hashbrown::hash_table::Entry::Occupied(occupied_entry) => {
+                ErasedEntry::Occupied(ErasedOccupiedEntry {
+                    slot: OccupiedSlot::Hashed(occupied_entry),
+                    storage: &self.storage,
+                })
+            }
+            hashbrown::hash_table::Entry::Vacant(vacant_entry) => {
+                ErasedEntry::Vacant(ErasedVacantEntry {
+                    slot: VacantSlot::Hashed(vacant_entry),
+                    storage: &self.storage,
+                })
+            }
+        }
+    }
+
+    /// Hashes a key with this map's hasher.
+    /// Safety: `key_ref` points to a value of `key_shape`, which has a hash fn
+    pub unsafe fn hash_key(&self, key_ref: ErasedKeyRef<'_>, key_shape: &Shape) -> u64
+    where
+        S: BuildHasher,
+    {
+        unsafe { make_hash(&self.hash_builder, key_ref.0, key_shape) }
+    }
+
+    pub fn entries(&self) -> ErasedIter<'_> {
+        ErasedIter {
+            hashed: self.hash_table.iter(),
+            sorted: self.sorted_entries.iter(),
+        }
+    }
+
+    pub fn entries_mut(&mut self) -> ErasedIterMut<'_> {
+        self.generation += 1;
+        ErasedIterMut {
+            hashed: self.hash_table.iter_mut(),
+            sorted: self.sorted_entries.iter_mut(),
+        }
+    }
+
+    /// Consumes the map without dropping the keys and values, which the
+    /// caller takes over.
+    pub fn into_entries(self) -> ErasedIntoIter<B> {
+        ErasedIntoIter {
+            hashed: self.hash_table.into_iter(),
+            sorted: self.sorted_entries.into_iter(),
+            storage: self.storage,
+        }
+    }
+
+    #[cfg(feature = "entry-timestamps")]
+    pub fn entries_with_meta(&self) -> impl Iterator<Item = (&ErasedKey, &ErasedValue, EntryMeta)> {
+        self.hash_table
+            .iter()
+            .chain(&self.sorted_entries)
+            .map(|entry| (&entry.key, &entry.value, entry.meta))
+    }
+
+    /// Moves every entry of `other` into this map, moving boxed keys and
+    /// values into this map's storage. Entries of `other` replace existing
+    /// entries with equal keys. Every key is rehashed with this map's hasher.
+    /// Safety: `key_shape` and `value_shape` are the correct shapes for both maps.
+    pub unsafe fn merge_from<B2: StorageBackend>(
+        &mut self,
+        mut other: ErasedHashMap<S, B2>,
+        key_shape: &Shape,
+        value_shape: &Shape,
+    ) where
+        S: BuildHasher,
+    {
+        let entries = other
+            .hash_table
+            .drain()
+            .chain(other.sorted_entries.drain(..));
+        unsafe { self.reserve(entries.size_hint().0, key_shape) };
+
+        for HashTableEntry { key, value, .. } in entries {
+            unsafe {
+                let key = ErasedKey(key.0.move_to(key_shape, &other.storage, &self.storage));
+                let value =
+                    ErasedValue(value.0.move_to(value_shape, &other.storage, &self.storage));
+                if let Some(old_value) = self.insert(key, key_shape, value) {
+                    Self::drop_erased(&self.storage, old_value.0, value_shape);
+                }
+            }
+        }
+    }
+
+    /// Like `merge_from`, but when both maps have an entry for a key, its
+    /// value becomes `resolve(storage, key, left, right)`, where `left` is
+    /// this map's value and `right` is `other`'s.
+    /// Safety: `key_shape` and `value_shape` are the correct shapes for both
+    ///         maps, and `resolve` must return a value of `value_shape` from
+    ///         this map's storage
+    pub unsafe fn merge_with<B2: StorageBackend>(
+        &mut self,
+        mut other: ErasedHashMap<S, B2>,
+        key_shape: &Shape,
+        value_shape: &Shape,
+        mut resolve: impl FnMut(&B, &ErasedKey, ErasedValue, ErasedValue) -> ErasedValue,
+    ) where
+        S: BuildHasher,
+    {
+        let key_storage = self.storage.storage_for(key_shape);
+        let entries = other
+            .hash_table
+            .drain()
+            .chain(other.sorted_entries.drain(..));
+
+        for HashTableEntry { key, value, .. } in entries {
+            unsafe {
+                let key = ErasedKey(key.0.move_to(key_shape, &other.storage, &self.storage));
+                let right =
+                    ErasedValue(value.0.move_to(value_shape, &other.storage, &self.storage));
+                let key_ref = ErasedKeyRef(key.as_ptr(key_storage));
 
-        let value = self.hash_table.find(hash, eq);
+                // the entry is taken out while `resolve` runs, so if it panics
+                // the table doesn't still own `left`
+                let Some((existing_key, left)) = self.remove(key_ref, key_shape) else {
+                    self.insert(key, key_shape, right);
+                    continue;
+                };
+                Self::drop_erased(&self.storage, key.0, key_shape);
+                let resolved = resolve(&self.storage, &existing_key, left, right);
+                self.insert(existing_key, key_shape, resolved);
+            }
+        }
+    }
+
+    /// Shrinks the table to fit, first relocating the boxed keys and values
+    /// into a fresh backend if the backend is [`StorageBackend::relocatable`].
+    /// Safety: `key_shape` and `value_shape` are the correct shapes.
+    pub unsafe fn compact(&mut self, key_shape: &Shape, value_shape: &Shape)
+    where
+        S: BuildHasher,
+    {
+        if self.storage.relocatable() {
+            unsafe { self.relocate(key_shape, value_shape) };
+        }
+
+        if KeyIndex::for_shape(key_shape) == KeyIndex::Hashed {
+            let key_storage = self.storage.storage_for(key_shape);
+            self.hash_table.shrink_to_fit(unsafe {
+                make_table_entry_hasher(&self.hash_builder, key_shape, key_storage)
+            });
+        }
+        self.sorted_entries.shrink_to_fit();
+    }
+
+    /// Moves every boxed key and value into the backend's
+    /// [`StorageBackend::fresh`] copy, dropping the old backend.
+    /// Safety: `key_shape` and `value_shape` are the correct shapes.
+    unsafe fn relocate(&mut self, key_shape: &Shape, value_shape: &Shape) {
+        let fresh_storage = self.storage.fresh();
+        let old_storage = std::mem::replace(&mut self.storage, fresh_storage);
+        let entries = self
+            .hash_table
+            .iter_mut()
+            .chain(self.sorted_entries.iter_mut());
+        for HashTableEntry { key, value, .. } in entries {
+            unsafe {
+                let moved_key =
+                    std::ptr::read(&key.0).move_to(key_shape, &old_storage, &self.storage);
+                std::ptr::write(&mut key.0, moved_key);
+                let moved_value =
+                    std::ptr::read(&value.0).move_to(value_shape, &old_storage, &self.storage);
+                std::ptr::write(&mut value.0, moved_value);
+            }
+        }
+        drop(old_storage);
+    }
+
+    unsafe fn drop_erased(storage: &B, mut erased: Erased, shape: &Shape) {
+        if let Some(drop_fn) = Erased::drop_fn(shape, storage) {
+            drop_fn(&mut erased);
+        }
+    }
+
+    /// Binary searches `sorted_entries` for `key_ref`, returning where it is
+    /// or where it would be inserted.
+    unsafe fn sorted_position(
+        &self,
+        key_ref: PtrConst<'_>,
+        key_shape: &Shape,
+        key_storage: ErasedStorage,
+    ) -> Result<usize, usize> {
+        let key_vtable = KeyVTable::for_shape(key_shape);
+        self.sorted_entries.binary_search_by(|entry| unsafe {
+            key_vtable.cmp(entry.key.as_ptr(key_storage), key_ref)
+        })
+    }
+
+    /// Removes and drops every entry for which `keep` returns false.
+    /// Safety: `key_shape` and `value_shape` are the correct shapes.
+    pub unsafe fn retain(
+        &mut self,
+        key_shape: &Shape,
+        value_shape: &Shape,
+        mut keep: impl FnMut(&ErasedKey, &ErasedValue) -> bool,
+    ) {
+        let storage = &self.storage;
+        let mut removed = false;
+        let mut keep_or_drop = |entry: &mut HashTableEntry| {
+            if keep(&entry.key, &entry.value) {
+                return true;
+            }
+            unsafe {
+                Self::drop_erased(storage, std::ptr::read(&entry.key.0), key_shape);
+                Self::drop_erased(storage, std::ptr::read(&entry.value.0), value_shape);
+            }
+            removed = true;
+            false
+        };
+        self.hash_table.retain(&mut keep_or_drop);
+        self.sorted_entries.retain_mut(keep_or_drop);
+        if removed {
+            self.generation += 1;
+        }
+    }
 
-        value.map(|hash_table_entry| &hash_table_entry.value)
+    /// Drops all keys and values and empties the table, keeping its capacity.
+    /// Safety: `key_shape` and `value_shape` are the correct shapes.
+    pub unsafe fn clear(&mut self, key_shape: &Shape, value_shape: &Shape) {
+        unsafe { Self::drop_keys_and_values(self, key_shape, value_shape) };
+        self.hash_table.clear();
+        self.sorted_entries.clear();
+        self.generation += 1;
     }
 
     /// Drops the keys and values in the hash map, which requires the shapes
@@ -104,11 +999,18 @@ impl<S> ErasedHashMap<S> {
     /// Safety: `this` is a valid pointer and `key_shape` and `value_shape` are the
     ///         the correct shapes.
     pub unsafe fn drop_keys_and_values(this: *mut Self, key_shape: &Shape, value_shape: &Shape) {
-        let drop_key = Erased::drop_fn(key_shape);
-        let drop_value = Erased::drop_fn(value_shape);
+        let storage = unsafe { &(*this).storage };
+        let drop_key = Erased::drop_fn(key_shape, storage);
+        let drop_value = Erased::drop_fn(value_shape, storage);
 
         if drop_key.is_some() || drop_value.is_some() {
-            for hash_table_entry in unsafe { (*this).hash_table.iter_mut() } {
+            let entries = unsafe {
+                (*this)
+                    .hash_table
+                    .iter_mut()
+                    .chain((*this).sorted_entries.iter_mut())
+            };
+            for hash_table_entry in entries {
                 if let Some(drop_key) = &drop_key {
                     drop_key(&mut hash_table_entry.key.0);
                 }
@@ -123,19 +1025,22 @@ impl<S> ErasedHashMap<S> {
 unsafe fn make_eq<'a>(
     key_ref: PtrConst<'a>,
     key_shape: &'a Shape,
+    key_storage: ErasedStorage,
 ) -> impl FnMut(&HashTableEntry) -> bool + 'a {
-    let eq = (key_shape.vtable.partial_eq)().unwrap();
-    move |hash_table_entry| unsafe { eq(key_ref, hash_table_entry.key.as_ptr(key_shape)) }
+    let key_vtable = KeyVTable::for_shape(key_shape);
+    move |hash_table_entry| unsafe {
+        key_vtable.eq(key_ref, hash_table_entry.key.as_ptr(key_storage))
+    }
 }
 
-unsafe fn make_hash<S>(hash_builder: &S, key_ref: PtrConst, key_shape: &Shape) -> u64
+pub(crate) unsafe fn make_hash<S>(hash_builder: &S, key_ref: PtrConst, key_shape: &Shape) -> u64
 where
     S: BuildHasher,
 {
     unsafe { make_key_ref_hasher(hash_builder, key_shape)(key_ref) }
 }
 
-unsafe fn make_key_ref_hasher<'a, S>(
+pub(crate) unsafe fn make_key_ref_hasher<'a, S>(
     hash_builder: &'a S,
     key_shape: &'a Shape,
 ) -> impl Fn(PtrConst) -> u64 + 'a
@@ -147,22 +1052,53 @@ where
         hasher.write(bytes)
     };
 
-    let hash_fn: HashFn = (key_shape.vtable.hash)().unwrap();
+    let key_vtable = KeyVTable::for_shape(key_shape);
+    let hash_fn: HashFn = key_vtable.hash.unwrap();
 
     move |key_ref| {
         let mut hasher = hash_builder.build_hasher();
 
         unsafe {
-            hash_fn(key_ref, PtrMut::new(&mut hasher), hasher_write_fn);
+            hash_fn(
+                key_vtable.target(key_ref),
+                PtrMut::new(&mut hasher),
+                hasher_write_fn,
+            );
         }
 
         hasher.finish()
     }
 }
 
+/// Hashes a typed value the way `make_key_ref_hasher` hashes a value of its
+/// shape: shape hash fns only forward `write` to the hasher, so the other
+/// `Hasher` methods must fall back to it here too.
+pub(crate) fn hash_typed<S, Q>(hash_builder: &S, value: &Q) -> u64
+where
+    S: BuildHasher,
+    Q: std::hash::Hash + ?Sized,
+{
+    struct WriteOnly<H>(H);
+
+    impl<H: Hasher> Hasher for WriteOnly<H> {
+        fn write(&mut self, bytes: &[u8]) {
+            self.0.write(bytes)
+        }
+
+        fn finish(&self) -> u64 {
+            self.0.finish()
+        }
+    }
+
+    let mut hasher = WriteOnly(hash_builder.build_hasher());
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
 unsafe fn make_table_entry_hasher<'a, S>(
     hash_builder: &'a S,
     key_shape: &'a Shape,
+    key_storage: ErasedStorage,
 ) -> impl Fn(&HashTableEntry) -> u64 + 'a
 where
     S: BuildHasher,
@@ -170,6 +1106,6 @@ where
     unsafe {
         let key_ref_hasher = make_key_ref_hasher(hash_builder, key_shape);
 
-        move |hash_table_entry| key_ref_hasher(hash_table_entry.key.as_ptr(key_shape))
+        move |hash_table_entry| key_ref_hasher(hash_table_entry.key.as_ptr(key_storage))
     }
 }
@@ -0,0 +1,131 @@
+use std::hash::BuildHasher;
+
+use facet::{PtrConst, Shape};
+use hashbrown::HashTable;
+
+use crate::erased::{Erased, ErasedStorage};
+use crate::erased_hashmap::{ErasedKeyRef, ErasedValue, KeyVTable, make_hash, make_key_ref_hasher};
+use crate::storage::{DefaultStorage, StorageBackend};
+
+/// Finds the key inside an erased value.
+pub type KeyProjection = Box<dyn for<'v> Fn(PtrConst<'v>) -> PtrConst<'v>>;
+
+/// Returns a projection to the field at the dotted `path` in `value_shape`,
+/// along with the shape of that field.
+pub fn field_path_projection<'s>(
+    value_shape: &'s Shape<'s>,
+    path: &str,
+) -> Option<(KeyProjection, &'s Shape<'s>)> {
+    let mut offset = 0;
+    let mut shape = value_shape;
+
+    for name in path.split('.') {
+        let facet::Type::User(facet::UserType::Struct(struct_type)) = shape.ty else {
+            return None;
+        };
+        let field = struct_type.fields.iter().find(|field| field.name == name)?;
+        offset += field.offset;
+        shape = field.shape();
+    }
+
+    let projection: KeyProjection = Box::new(move |value_ref| unsafe { value_ref.field(offset) });
+    Some((projection, shape))
+}
+
+/// A hash table of erased values, where each value's key is found by
+/// projecting into the value instead of being stored separately.
+pub struct ErasedProjectedMap<S, B = DefaultStorage> {
+    hash_table: HashTable<ErasedValue>,
+    project_key: KeyProjection,
+    hash_builder: S,
+    storage: B,
+}
+
+impl<S, B: StorageBackend> ErasedProjectedMap<S, B> {
+    pub fn new(project_key: KeyProjection, hash_builder: S, storage: B) -> Self {
+        Self {
+            hash_table: HashTable::new(),
+            project_key,
+            hash_builder,
+            storage,
+        }
+    }
+
+    pub fn storage(&self) -> &B {
+        &self.storage
+    }
+
+    /// Safety: `value` must have `value_shape`, and its projected key must
+    ///         have `key_shape`
+    #[inline(never)]
+    pub unsafe fn insert(
+        &mut self,
+        value: ErasedValue,
+        key_shape: &Shape,
+        value_shape: &Shape,
+    ) -> Option<ErasedValue>
+    where
+        S: BuildHasher,
+    {
+        let value_storage = self.storage.storage_for(value_shape);
+        let key_ref = (self.project_key)(unsafe { value.as_ptr(value_storage) });
+        let hash = unsafe { make_hash(&self.hash_builder, key_ref, key_shape) };
+
+        let project_key = &self.project_key;
+        let key_ref_hasher = unsafe { make_key_ref_hasher(&self.hash_builder, key_shape) };
+
+        match self.hash_table.entry(
+            hash,
+            unsafe { make_eq(project_key, key_ref, key_shape, value_storage) },
+            |value| key_ref_hasher(project_key(unsafe { value.as_ptr(value_storage) })),
+        ) {
+            hashbrown::hash_table::Entry::Occupied(occupied_entry) => {
+                Some(std::mem::replace(occupied_entry.into_mut(), value))
+            }
+            hashbrown::hash_table::Entry::Vacant(vacant_entry) => {
+                vacant_entry.insert(value);
+                None
+            }
+        }
+    }
+
+    #[inline(never)]
+    pub unsafe fn get<'a>(
+        &'a self,
+        key_ref: ErasedKeyRef<'_>,
+        key_shape: &Shape,
+        value_shape: &Shape,
+    ) -> Option<&'a ErasedValue>
+    where
+        S: BuildHasher,
+    {
+        let value_storage = self.storage.storage_for(value_shape);
+        let hash = unsafe { make_hash(&self.hash_builder, key_ref.0, key_shape) };
+        let eq = unsafe { make_eq(&self.project_key, key_ref.0, key_shape, value_storage) };
+
+        self.hash_table.find(hash, eq)
+    }
+
+    /// Drops the values in the map, which requires the value shape and
+    /// cannot be done in the Drop impl for this struct.
+    /// Safety: `this` is a valid pointer and `value_shape` is the correct shape.
+    pub unsafe fn drop_values(this: *mut Self, value_shape: &Shape) {
+        let storage = unsafe { &(*this).storage };
+
+        if let Some(drop_value) = Erased::drop_fn(value_shape, storage) {
+            for value in unsafe { (*this).hash_table.iter_mut() } {
+                drop_value(&mut value.0);
+            }
+        }
+    }
+}
+
+unsafe fn make_eq<'a>(
+    project_key: &'a KeyProjection,
+    key_ref: PtrConst<'a>,
+    key_shape: &'a Shape,
+    value_storage: ErasedStorage,
+) -> impl FnMut(&ErasedValue) -> bool + 'a {
+    let key_vtable = KeyVTable::for_shape(key_shape);
+    move |value| unsafe { key_vtable.eq(key_ref, project_key(value.as_ptr(value_storage))) }
+}
@@ -0,0 +1,111 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+
+use facet::{Facet, PtrConst};
+
+use crate::erased::Erased;
+use crate::erased_alias_map::ErasedAliasMap;
+use crate::erased_hashmap::{ErasedKey, ErasedKeyRef, ErasedValue};
+use crate::storage::{DefaultStorage, StorageBackend};
+
+/// A map where each value can be reached by several keys (aliases). The
+/// value is stored once, and removing it through any key removes all of
+/// its keys.
+pub struct FacetAliasMap<
+    'a,
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S = hashbrown::DefaultHashBuilder,
+    B: StorageBackend = DefaultStorage,
+> {
+    alias_map: ErasedAliasMap<S, B>,
+    _marker: std::marker::PhantomData<(K, V, &'a ())>,
+}
+
+impl<'a, K, V, S, B> Default for FacetAliasMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S: Default,
+    B: StorageBackend + Default,
+{
+    fn default() -> Self {
+        Self {
+            alias_map: ErasedAliasMap::default(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, S, B> Drop for FacetAliasMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    fn drop(&mut self) {
+        unsafe {
+            ErasedAliasMap::drop_keys_and_values(&mut self.alias_map, K::SHAPE, V::SHAPE);
+        }
+    }
+}
+
+impl<'a, K, V, S, B> FacetAliasMap<'a, K, V, S, B>
+where
+    K: Facet<'a> + Hash + Eq,
+    V: Facet<'a>,
+    S: BuildHasher,
+    B: StorageBackend,
+{
+    /// Inserts `value` under `key`. If `key` is already present, the value
+    /// shared by it and its aliases is replaced and returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let erased_key = ErasedKey(Erased::new(key, self.alias_map.storage()));
+        let erased_value = ErasedValue(Erased::new(value, self.alias_map.storage()));
+        let old_erased_value = unsafe { self.alias_map.insert(erased_key, K::SHAPE, erased_value) };
+
+        old_erased_value
+            .map(|old_value| unsafe { old_value.0.into_typed(self.alias_map.storage()) })
+    }
+
+    /// Makes `alias` reach the same value as `key`. Returns `alias` back if
+    /// `key` is missing or `alias` is already in the map.
+    pub fn add_alias<Q: Borrow<K>>(&mut self, key: &Q, alias: K) -> Result<(), K> {
+        let key_ref = PtrConst::new(key.borrow());
+        let erased_alias = ErasedKey(Erased::new(alias, self.alias_map.storage()));
+
+        unsafe {
+            self.alias_map
+                .add_alias(ErasedKeyRef(key_ref), erased_alias, K::SHAPE)
+                .map_err(|alias| alias.0.into_typed(self.alias_map.storage()))
+        }
+    }
+
+    pub fn get<'b, Q: Borrow<K>>(&'b self, key: &Q) -> Option<&'b V> {
+        let key_ref = PtrConst::new(key.borrow());
+        let value_storage = self.alias_map.storage().storage_for(V::SHAPE);
+
+        unsafe {
+            self.alias_map
+                .get(ErasedKeyRef(key_ref), K::SHAPE)
+                .map(|value| value.0.as_ptr(value_storage).get())
+        }
+    }
+
+    /// How many keys reach the same value as `key`, including `key` itself.
+    pub fn alias_count<Q: Borrow<K>>(&self, key: &Q) -> usize {
+        let key_ref = PtrConst::new(key.borrow());
+        unsafe { self.alias_map.alias_count(ErasedKeyRef(key_ref), K::SHAPE) }
+    }
+
+    /// Removes the value `key` reaches, along with all of its aliases.
+    pub fn remove<Q: Borrow<K>>(&mut self, key: &Q) -> Option<V> {
+        let key_ref = PtrConst::new(key.borrow());
+
+        unsafe {
+            self.alias_map
+                .remove(ErasedKeyRef(key_ref), K::SHAPE)
+                .map(|value| value.0.into_typed(self.alias_map.storage()))
+        }
+    }
+}
@@ -1,21 +1,144 @@
 use std::borrow::Borrow;
+use std::fmt;
 use std::hash::{BuildHasher, Hash};
 
-use facet::{Facet, PtrConst};
+use facet::{
+    Def, DisplayFn, Facet, PtrConst, PtrUninit, Shape, Type, TypeParam, UserType, ValueVTable,
+    value_vtable,
+};
 
+use crate::dyn_map::{MergeStrategy, deep_merge_typed};
+use crate::entry::{Entry, EntryRef, OccupiedEntry, VacantEntry, VacantEntryRef};
 use crate::erased::Erased;
-use crate::erased_hashmap::{ErasedHashMap, ErasedKey, ErasedKeyRef, ErasedValue};
+#[cfg(feature = "entry-timestamps")]
+use crate::erased_hashmap::EntryMeta;
+use crate::erased_hashmap::{
+    ErasedEntry, ErasedHashMap, ErasedIntoIter, ErasedKey, ErasedKeyRef, ErasedValue, KeyVTable,
+};
+use crate::facet_value::FacetValue;
+use crate::iter::{IntoIter, IntoKeys, IntoValues, Iter, IterMut, Keys, Values, ValuesMut};
+use crate::storage::{ArenaStorage, DefaultStorage, StorageBackend, StorageReport};
 
-#[derive(Default)]
-pub struct FacetHashMap<'a, K: Facet<'a>, V: Facet<'a>, S = hashbrown::DefaultHashBuilder> {
-    hash_map: ErasedHashMap<S>,
+/// The keys that differ between two versions of a map, as returned by
+/// [`FacetHashMap::replace_contents`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeSet<K> {
+    pub added: Vec<K>,
+    pub removed: Vec<K>,
+    /// keys whose value isn't equal according to the value shape, or whose
+    /// value shape can't be compared
+    pub changed: Vec<K>,
+}
+
+impl<K> ChangeSet<K> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// The keys that appeared more than once in the input of
+/// [`FacetHashMap::from_iter_strict`] or [`FacetHashMap::extend_strict`],
+/// formatted with the key shape's debug fn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateKeys {
+    pub keys: Vec<String>,
+}
+
+impl fmt::Display for DuplicateKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "duplicate keys: {}", self.keys.join(", "))
+    }
+}
+
+impl std::error::Error for DuplicateKeys {}
+
+/// A hash map whose keys and values are handled through their facet shapes.
+///
+/// Enum keys with a primitive `#[repr]` are compared by tag before their
+/// `PartialEq` is called, so keys of different variants must never be equal.
+/// A derived `PartialEq` always satisfies this.
+pub struct FacetHashMap<
+    'a,
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S = hashbrown::DefaultHashBuilder,
+    B: StorageBackend = DefaultStorage,
+> {
+    hash_map: ErasedHashMap<S, B>,
     _marker: std::marker::PhantomData<(K, V, &'a ())>,
 }
 
-impl<'a, K, V, S> Drop for FacetHashMap<'a, K, V, S>
+impl<'a, K, V, S, B> Default for FacetHashMap<'a, K, V, S, B>
 where
     K: Facet<'a>,
     V: Facet<'a>,
+    S: Default,
+    B: StorageBackend + Default,
+{
+    fn default() -> Self {
+        Self::with_hasher_and_storage(S::default(), B::default())
+    }
+}
+
+// Safety: the map owns its keys and values, so it can move to another thread
+// whenever they (and the hasher and storage) can.
+unsafe impl<'a, K, V, S, B> Send for FacetHashMap<'a, K, V, S, B>
+where
+    K: Facet<'a> + Send,
+    V: Facet<'a> + Send,
+    S: Send,
+    B: StorageBackend + Send,
+{
+}
+
+// Safety: the shape describes the map as an opaque sized value, which is
+// all the vtable relies on
+unsafe impl<'a, K, V, S, B> Facet<'a> for FacetHashMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S: 'a,
+    B: StorageBackend + 'a,
+{
+    const VTABLE: &'static ValueVTable = &const {
+        value_vtable!(FacetHashMap<'a, K, V, S, B>, |f, opts| {
+            write!(f, "{}", Self::SHAPE.type_identifier)?;
+            if let Some(opts) = opts.for_children() {
+                write!(f, "<")?;
+                (K::SHAPE.vtable.type_name)(f, opts)?;
+                write!(f, ", ")?;
+                (V::SHAPE.vtable.type_name)(f, opts)?;
+                write!(f, ">")
+            } else {
+                write!(f, "<…>")
+            }
+        })
+    };
+
+    const SHAPE: &'static Shape<'static> = &const {
+        Shape::builder_for_sized::<Self>()
+            .type_identifier("FacetHashMap")
+            .type_params(&[
+                TypeParam {
+                    name: "K",
+                    shape: || K::SHAPE,
+                },
+                TypeParam {
+                    name: "V",
+                    shape: || V::SHAPE,
+                },
+            ])
+            .ty(Type::User(UserType::Opaque))
+            .def(Def::Undefined)
+            .build()
+    };
+}
+
+impl<'a, K, V, S, B> Drop for FacetHashMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    B: StorageBackend,
 {
     fn drop(&mut self) {
         unsafe {
@@ -24,35 +147,984 @@ where
     }
 }
 
-impl<'a, K, V, S> FacetHashMap<'a, K, V, S>
+impl<'a, K, V, S, B> FacetHashMap<'a, K, V, S, B>
 where
     K: Facet<'a>,
     V: Facet<'a>,
+    B: StorageBackend,
 {
+    pub fn with_hasher_and_storage(hash_builder: S, storage: B) -> Self {
+        Self {
+            hash_map: ErasedHashMap::with_hasher_and_storage(hash_builder, storage),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    pub fn storage(&self) -> &B {
+        self.hash_map.storage()
+    }
+
+    pub fn len(&self) -> usize {
+        self.hash_map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hash_map.is_empty()
+    }
+
+    /// Drops every entry, keeping the table's capacity for reuse.
+    pub fn clear(&mut self) {
+        unsafe { self.hash_map.clear(K::SHAPE, V::SHAPE) };
+    }
+
+    /// How many entries the map can hold without resizing.
+    pub fn capacity(&self) -> usize {
+        self.hash_map.capacity()
+    }
+
+    /// An empty map with room for `capacity` entries.
+    pub fn with_capacity(capacity: usize) -> Self
+    where
+        S: BuildHasher + Default,
+        B: Default,
+    {
+        let mut map = Self::default();
+        map.reserve(capacity);
+        map
+    }
+
+    /// Makes room for `additional` more entries, so inserting them won't
+    /// resize the table.
+    pub fn reserve(&mut self, additional: usize)
+    where
+        S: BuildHasher,
+    {
+        unsafe { self.hash_map.reserve(additional, K::SHAPE) };
+    }
+
+    /// A counter that goes up whenever the map may have changed, so a cache
+    /// built from the map is stale if the generation differs from when it
+    /// was built. Mutable lookups count as changes.
+    pub fn generation(&self) -> u64 {
+        self.hash_map.generation()
+    }
+
+    /// Keys are hashed if `K`'s shape supports it, and otherwise kept in
+    /// sorted order using the shape's `ord`.
     pub fn insert(&mut self, key: K, value: V) -> Option<V>
     where
-        K: Facet<'a> + Hash + Eq,
-        V: Facet<'a>,
         S: BuildHasher,
     {
-        let erased_key = ErasedKey(Erased::new(key));
-        let erased_value = ErasedValue(Erased::new(value));
+        let erased_key = ErasedKey(Erased::new(key, self.hash_map.storage()));
+        let erased_value = ErasedValue(Erased::new(value, self.hash_map.storage()));
         let old_erased_value = unsafe { self.hash_map.insert(erased_key, K::SHAPE, erased_value) };
 
-        old_erased_value.map(|old_value| unsafe { old_value.0.into_typed() })
+        old_erased_value.map(|old_value| unsafe { old_value.0.into_typed(self.hash_map.storage()) })
+    }
+
+    /// Looks up `key` once for inserting or changing its entry. If the
+    /// entry is occupied, `key` is dropped and the map's key is kept.
+    pub fn entry(&mut self, key: K) -> Entry<'_, 'a, K, V, B>
+    where
+        S: BuildHasher,
+    {
+        let key_ref = ErasedKeyRef(PtrConst::new(&key));
+        match unsafe { self.hash_map.entry(key_ref, K::SHAPE) } {
+            ErasedEntry::Occupied(entry) => Entry::Occupied(OccupiedEntry {
+                entry,
+                _marker: std::marker::PhantomData,
+            }),
+            ErasedEntry::Vacant(entry) => Entry::Vacant(VacantEntry {
+                key,
+                entry,
+                _marker: std::marker::PhantomData,
+            }),
+        }
+    }
+
+    /// Like [`Self::entry`], but looked up by a borrowed form of the key,
+    /// like `&str` for `String` keys, so that the owned key is only built
+    /// when inserting. Relies on `Q` hashing the same as `K`, as `Borrow`
+    /// requires.
+    pub fn entry_ref<'q, Q>(&mut self, key: &'q Q) -> EntryRef<'_, 'q, 'a, K, Q, V, B>
+    where
+        K: Borrow<Q> + From<&'q Q>,
+        Q: Hash + Eq + ?Sized,
+        S: BuildHasher,
+    {
+        let entry = unsafe {
+            self.hash_map.entry_borrowed(key, K::SHAPE, |candidate| {
+                candidate.get::<K>().borrow() == key
+            })
+        };
+        match entry {
+            ErasedEntry::Occupied(entry) => EntryRef::Occupied(OccupiedEntry {
+                entry,
+                _marker: std::marker::PhantomData,
+            }),
+            ErasedEntry::Vacant(entry) => EntryRef::Vacant(VacantEntryRef {
+                key,
+                entry,
+                _marker: std::marker::PhantomData,
+            }),
+        }
     }
 
     pub fn get<'b, Q: Borrow<K>>(&'b self, key: &Q) -> Option<&'b V>
     where
-        K: Hash + Eq,
         S: BuildHasher,
     {
         let key_ref = PtrConst::new(key.borrow());
 
+        let value_storage = self.hash_map.storage().storage_for(V::SHAPE);
+
         unsafe {
             self.hash_map
                 .get(ErasedKeyRef(key_ref), K::SHAPE)
-                .map(|value| value.0.as_ptr(V::SHAPE).get())
+                .map(|value| value.0.as_ptr(value_storage).get())
+        }
+    }
+
+    pub fn contains_key<Q: Borrow<K>>(&self, key: &Q) -> bool
+    where
+        S: BuildHasher,
+    {
+        let key_ref = PtrConst::new(key.borrow());
+        unsafe { self.hash_map.contains_key(ErasedKeyRef(key_ref), K::SHAPE) }
+    }
+
+    /// Swaps in `new_map` as the contents of this map, dropping the old
+    /// entries, and reports which keys were added, removed or changed.
+    pub fn replace_contents(&mut self, new_map: Self) -> ChangeSet<K>
+    where
+        K: Clone,
+        S: BuildHasher,
+    {
+        let value_eq = (V::SHAPE.vtable.partial_eq)();
+        let mut changes = ChangeSet {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        };
+
+        for (key, new_value) in new_map.iter() {
+            match self.get(key) {
+                None => changes.added.push(key.clone()),
+                Some(old_value) => {
+                    let equal = value_eq.is_some_and(|eq| unsafe {
+                        eq(PtrConst::new(old_value), PtrConst::new(new_value))
+                    });
+                    if !equal {
+                        changes.changed.push(key.clone());
+                    }
+                }
+            }
+        }
+        for (key, _) in self.iter() {
+            if new_map.get(key).is_none() {
+                changes.removed.push(key.clone());
+            }
+        }
+
+        let generation = self.hash_map.generation();
+        *self = new_map;
+        self.hash_map
+            .set_generation(generation.max(self.hash_map.generation()) + 1);
+        changes
+    }
+
+    /// Inserts each pair, calling `combine` with the existing value instead
+    /// when the key is already present. Each pair is looked up only once.
+    pub fn extend_with(
+        &mut self,
+        iter: impl IntoIterator<Item = (K, V)>,
+        mut combine: impl FnMut(&mut V, V),
+    ) where
+        S: BuildHasher,
+    {
+        let value_storage = self.hash_map.storage().storage_for(V::SHAPE);
+        for (key, value) in iter {
+            let erased_key = ErasedKey(Erased::new(key, self.hash_map.storage()));
+            let erased_value = ErasedValue(Erased::new(value, self.hash_map.storage()));
+            let occupied = unsafe {
+                self.hash_map
+                    .insert_or_get(erased_key, K::SHAPE, erased_value)
+            };
+            if let Some((existing, new_value)) = occupied {
+                let existing = unsafe { existing.0.as_mut_ptr(value_storage).as_mut() };
+                let new_value = unsafe { new_value.0.into_typed(self.hash_map.storage()) };
+                combine(existing, new_value);
+            }
+        }
+    }
+
+    /// Collects the pairs into a new map, failing if any key appears twice.
+    pub fn from_iter_strict(iter: impl IntoIterator<Item = (K, V)>) -> Result<Self, DuplicateKeys>
+    where
+        S: BuildHasher + Default,
+        B: Default,
+    {
+        let mut map = Self::default();
+        map.extend_strict(iter)?;
+        Ok(map)
+    }
+
+    /// Inserts each pair, failing if a key appears twice or is already in
+    /// the map. Every pair is still consumed: the first value for each key
+    /// is kept and later ones are dropped.
+    pub fn extend_strict(
+        &mut self,
+        iter: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), DuplicateKeys>
+    where
+        S: BuildHasher,
+    {
+        let mut duplicates = Vec::new();
+        for (key, value) in iter {
+            if self.get(&key).is_some() {
+                let key = format!("{:?}", ShapeDebug(PtrConst::new(&key), K::SHAPE));
+                if !duplicates.contains(&key) {
+                    duplicates.push(key);
+                }
+            } else {
+                self.insert(key, value);
+            }
+        }
+
+        if duplicates.is_empty() {
+            Ok(())
+        } else {
+            Err(DuplicateKeys { keys: duplicates })
+        }
+    }
+
+    /// Inserts `value` under `key` until the returned guard is dropped, which
+    /// puts back the value it displaced, or removes the key if there was none.
+    /// The map can be used through the guard in the meantime.
+    pub fn scoped_insert(&mut self, key: K, value: V) -> ScopedInsert<'_, 'a, K, V, S, B>
+    where
+        K: Clone,
+        S: BuildHasher,
+    {
+        let displaced = self.insert(key.clone(), value);
+        ScopedInsert {
+            map: self,
+            key: Some(key),
+            displaced,
+        }
+    }
+
+    /// Inserts each pair until one's value is an error, which is returned
+    /// along with its key. The pairs before it stay inserted.
+    pub fn try_extend<E>(
+        &mut self,
+        iter: impl IntoIterator<Item = (K, Result<V, E>)>,
+    ) -> Result<(), (K, E)>
+    where
+        S: BuildHasher,
+    {
+        for (key, value) in iter {
+            match value {
+                Ok(value) => {
+                    self.insert(key, value);
+                }
+                Err(error) => return Err((key, error)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Like [`Self::try_extend`], but inserts every pair whose value is ok
+    /// and returns all the errors with their keys.
+    pub fn try_extend_all<E>(
+        &mut self,
+        iter: impl IntoIterator<Item = (K, Result<V, E>)>,
+    ) -> Result<(), Vec<(K, E)>>
+    where
+        S: BuildHasher,
+    {
+        let mut errors = Vec::new();
+        for (key, value) in iter {
+            match value {
+                Ok(value) => {
+                    self.insert(key, value);
+                }
+                Err(error) => errors.push((key, error)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Moves every entry of `other` into this map without cloning them.
+    /// Entries of `other` replace existing entries with equal keys. Every key
+    /// is rehashed with this map's hasher, since `other`'s hasher may be
+    /// seeded differently even when `S` is the same type.
+    pub fn merge_from(&mut self, other: Self)
+    where
+        S: BuildHasher,
+    {
+        let other = std::mem::ManuallyDrop::new(other);
+        let other_hash_map = unsafe { std::ptr::read(&other.hash_map) };
+        unsafe { self.hash_map.merge_from(other_hash_map, K::SHAPE, V::SHAPE) };
+    }
+
+    /// Unions this map with `other`, moving the entries of both. When both
+    /// have a key, its value becomes `resolver(key, left, right)`, where
+    /// `left` comes from `self`.
+    pub fn merge(mut self, other: Self, mut resolver: impl FnMut(&K, V, V) -> V) -> Self
+    where
+        S: BuildHasher,
+    {
+        let other = std::mem::ManuallyDrop::new(other);
+        let other_hash_map = unsafe { std::ptr::read(&other.hash_map) };
+        let key_storage = self.hash_map.storage().storage_for(K::SHAPE);
+
+        unsafe {
+            self.hash_map.merge_with(
+                other_hash_map,
+                K::SHAPE,
+                V::SHAPE,
+                |storage, key, left, right| {
+                    let resolved = resolver(
+                        key.as_ptr(key_storage).get(),
+                        left.0.into_typed(storage),
+                        right.0.into_typed(storage),
+                    );
+                    ErasedValue(Erased::new(resolved, storage))
+                },
+            )
+        };
+        self
+    }
+
+    /// Shrinks the table to fit its entries and re-packs boxed keys and
+    /// values into a [`StorageBackend::fresh`] backend, which for
+    /// [`ArenaStorage`] and [`crate::PooledStorage`] releases the memory
+    /// freed by earlier removals. Keys and values stay where they are if the
+    /// backend isn't [`StorageBackend::relocatable`].
+    pub fn compact(&mut self)
+    where
+        S: BuildHasher,
+    {
+        unsafe { self.hash_map.compact(K::SHAPE, V::SHAPE) };
+    }
+
+    /// Builds one map per input on its own thread with `build`, then merges
+    /// them in input order with [`Self::merge_from`].
+    pub fn build_per_thread<I, F>(inputs: impl IntoIterator<Item = I>, build: F) -> Self
+    where
+        I: Send,
+        F: Fn(I) -> Self + Sync,
+        Self: Send,
+        S: BuildHasher + Default,
+        B: Default,
+    {
+        let maps: Vec<Self> = std::thread::scope(|scope| {
+            let handles: Vec<_> = inputs
+                .into_iter()
+                .map(|input| scope.spawn(|| build(input)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+
+        let mut merged = Self::default();
+        for map in maps {
+            merged.merge_from(map);
+        }
+        merged
+    }
+
+    /// Clones every entry through the key and value shapes into a vector,
+    /// in iteration order.
+    ///
+    /// Panics if either shape can't be cloned.
+    pub fn to_vec(&self) -> Vec<(K, V)> {
+        let clone_key = clone_fn::<K>();
+        let clone_value = clone_fn::<V>();
+        self.iter()
+            .map(|(key, value)| (clone_key(key), clone_value(value)))
+            .collect()
+    }
+
+    /// Like [`Self::to_vec`], but ordered by the key shape's `ord`.
+    ///
+    /// Panics if the key shape has no `ord`.
+    pub fn to_sorted_vec(&self) -> Vec<(K, V)> {
+        let key_vtable = KeyVTable::for_shape(K::SHAPE);
+        if key_vtable.ord.is_none() {
+            panic!("key shape `{}` has no ord", K::SHAPE);
+        }
+        let mut entries = self.to_vec();
+        entries.sort_by(|(a, _), (b, _)| unsafe {
+            key_vtable.cmp(PtrConst::new(a), PtrConst::new(b))
+        });
+        entries
+    }
+
+    /// Picks `k` entries uniformly at random in a single pass (reservoir
+    /// sampling). `rng` must return uniformly distributed `u64`s.
+    pub fn sample_iter(
+        &self,
+        mut rng: impl FnMut() -> u64,
+        k: usize,
+    ) -> impl Iterator<Item = (&K, &V)> {
+        let mut reservoir = Vec::with_capacity(k.min(self.len()));
+        for (seen, entry) in self.iter().enumerate() {
+            if seen < k {
+                reservoir.push(entry);
+            } else {
+                let slot = (rng() % (seen as u64 + 1)) as usize;
+                if slot < k {
+                    reservoir[slot] = entry;
+                }
+            }
+        }
+        reservoir.into_iter()
+    }
+
+    /// Iterates over the entries whose key hash starts with the
+    /// `prefix_bits`-bit number `partition`, so `2^prefix_bits` calls cover
+    /// every entry exactly once. Partitions are only stable across maps
+    /// built with the same hasher.
+    ///
+    /// Panics if `prefix_bits` is over 64, `partition` is out of range, or
+    /// the key shape can't be hashed.
+    pub fn iter_partition(&self, prefix_bits: u32, partition: u64) -> impl Iterator<Item = (&K, &V)>
+    where
+        S: BuildHasher,
+    {
+        assert!(prefix_bits <= 64, "prefix_bits must be at most 64");
+        assert!(
+            prefix_bits == 64 || partition < 1 << prefix_bits,
+            "partition {partition} is out of range for {prefix_bits} prefix bits"
+        );
+        if KeyVTable::for_shape(K::SHAPE).hash.is_none() {
+            panic!("key shape `{}` can't be hashed", K::SHAPE);
+        }
+
+        self.iter().filter(move |(key, _)| {
+            let hash = unsafe {
+                self.hash_map
+                    .hash_key(ErasedKeyRef(PtrConst::new(*key)), K::SHAPE)
+            };
+            hash.checked_shr(64 - prefix_bits).unwrap_or(0) == partition
+        })
+    }
+
+    /// Clones the map with each key rendered by the key shape's display fn.
+    /// Keys that render the same collapse into one entry.
+    ///
+    /// Panics if the key shape has no display fn.
+    pub fn to_string_keyed<S2>(&self) -> FacetHashMap<'a, String, V, S2>
+    where
+        V: Clone,
+        S2: BuildHasher + Default,
+    {
+        let display = (K::SHAPE.vtable.display)()
+            .unwrap_or_else(|| panic!("key shape `{}` can't be displayed", K::SHAPE));
+
+        let mut map = FacetHashMap::default();
+        for (key, value) in self.iter() {
+            let key = ShapeDisplay(PtrConst::new(key), display).to_string();
+            map.insert(key, value.clone());
+        }
+        map
+    }
+
+    /// Renders the entries as an aligned text table, one row per entry
+    /// sorted by the key shape's `ord`, or by the rendered key if it has
+    /// none. The first column is the key, and struct values get a
+    /// column per field; other values go in a single `value` column. Cells
+    /// use the shape's display fn, falling back to its debug fn.
+    pub fn render_table(&self) -> String {
+        let fields = match V::SHAPE.ty {
+            Type::User(UserType::Struct(struct_type)) if !struct_type.fields.is_empty() => {
+                struct_type.fields
+            }
+            _ => &[],
+        };
+
+        let mut header = vec!["key".to_string()];
+        if fields.is_empty() {
+            header.push("value".to_string());
+        } else {
+            header.extend(fields.iter().map(|field| field.name.to_string()));
+        }
+
+        let key_vtable = KeyVTable::for_shape(K::SHAPE);
+        let mut entries: Vec<_> = self.iter().collect();
+        if key_vtable.ord.is_some() {
+            entries.sort_by(|(a, _), (b, _)| unsafe {
+                key_vtable.cmp(PtrConst::new(*a), PtrConst::new(*b))
+            });
+        }
+
+        let mut rows: Vec<Vec<String>> = entries
+            .into_iter()
+            .map(|(key, value)| {
+                let value = PtrConst::new(value);
+                let mut row = vec![render_cell(PtrConst::new(key), K::SHAPE)];
+                if fields.is_empty() {
+                    row.push(render_cell(value, V::SHAPE));
+                } else {
+                    row.extend(fields.iter().map(|field| {
+                        render_cell(unsafe { value.field(field.offset) }, field.shape())
+                    }));
+                }
+                row
+            })
+            .collect();
+        if key_vtable.ord.is_none() {
+            rows.sort();
+        }
+
+        let mut widths = vec![0; header.len()];
+        for row in std::iter::once(&header).chain(&rows) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let mut table = String::new();
+        let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+        for row in [&header, &separator].into_iter().chain(&rows) {
+            let mut line = String::new();
+            for (cell, width) in row.iter().zip(&widths) {
+                line.push_str(&format!("{cell:width$}  "));
+            }
+            table.push_str(line.trim_end());
+            table.push('\n');
+        }
+        table
+    }
+
+    /// Counts the keys and values stored inline and boxed, with the sizes of
+    /// the boxed ones. For [`FacetValue`] values, the values they hold are
+    /// counted too.
+    pub fn storage_report(&self) -> StorageReport {
+        let storage = self.hash_map.storage();
+        let len = self.len();
+
+        let mut report = StorageReport::default();
+        report.record(K::SHAPE, storage.storage_for(K::SHAPE), len);
+        report.record(V::SHAPE, storage.storage_for(V::SHAPE), len);
+        if V::SHAPE.is_shape(FacetValue::SHAPE) {
+            for (_, value) in self.iter() {
+                let value: &FacetValue = unsafe { PtrConst::new(value).get() };
+                report.record(value.shape(), value.storage(), 1);
+            }
+        }
+        report
+    }
+
+    #[cfg(feature = "entry-timestamps")]
+    pub fn get_meta<Q: Borrow<K>>(&self, key: &Q) -> Option<EntryMeta>
+    where
+        S: BuildHasher,
+    {
+        let key_ref = PtrConst::new(key.borrow());
+        unsafe { self.hash_map.get_meta(ErasedKeyRef(key_ref), K::SHAPE) }
+    }
+
+    /// Iterates over the entries along with when they were inserted and
+    /// last changed.
+    #[cfg(feature = "entry-timestamps")]
+    pub fn iter_meta(&self) -> impl Iterator<Item = (&K, &V, EntryMeta)> {
+        let key_storage = self.hash_map.storage().storage_for(K::SHAPE);
+        let value_storage = self.hash_map.storage().storage_for(V::SHAPE);
+
+        self.hash_map
+            .entries_with_meta()
+            .map(move |(key, value, meta)| unsafe {
+                (
+                    key.as_ptr(key_storage).get(),
+                    value.as_ptr(value_storage).get(),
+                    meta,
+                )
+            })
+    }
+
+    /// Looks up a key by a borrowed form of it, like `&Path` for `PathBuf`
+    /// or `&str` for `String` keys, without building a `K`. Relies on `Q`
+    /// hashing the same as `K`, as `Borrow` requires.
+    pub fn get_borrowed<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        S: BuildHasher,
+    {
+        let value_storage = self.hash_map.storage().storage_for(V::SHAPE);
+
+        unsafe {
+            self.hash_map
+                .find(key, K::SHAPE, |candidate| {
+                    candidate.get::<K>().borrow() == key
+                })
+                .map(|value| value.as_ptr(value_storage).get())
+        }
+    }
+
+    /// Like [`Self::get_borrowed`], for checking membership.
+    pub fn contains_key_borrowed<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        S: BuildHasher,
+    {
+        self.get_borrowed(key).is_some()
+    }
+
+    pub fn get_mut<'b, Q: Borrow<K>>(&'b mut self, key: &Q) -> Option<&'b mut V>
+    where
+        S: BuildHasher,
+    {
+        let key_ref = PtrConst::new(key.borrow());
+
+        let value_storage = self.hash_map.storage().storage_for(V::SHAPE);
+
+        unsafe {
+            self.hash_map
+                .get_mut(ErasedKeyRef(key_ref), K::SHAPE)
+                .map(|value| value.0.as_mut_ptr(value_storage).as_mut())
+        }
+    }
+
+    /// Removes the entry for `key`, dropping the stored key and returning the
+    /// value.
+    pub fn remove<Q: Borrow<K>>(&mut self, key: &Q) -> Option<V>
+    where
+        S: BuildHasher,
+    {
+        let key_ref = PtrConst::new(key.borrow());
+
+        let (mut erased_key, erased_value) =
+            unsafe { self.hash_map.remove(ErasedKeyRef(key_ref), K::SHAPE)? };
+        if let Some(drop_key) = Erased::drop_fn(K::SHAPE, self.hash_map.storage()) {
+            drop_key(&mut erased_key.0);
+        }
+
+        Some(unsafe { erased_value.0.into_typed(self.hash_map.storage()) })
+    }
+
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&K, &V) -> bool) {
+        let key_storage = self.hash_map.storage().storage_for(K::SHAPE);
+        let value_storage = self.hash_map.storage().storage_for(V::SHAPE);
+
+        unsafe {
+            self.hash_map.retain(K::SHAPE, V::SHAPE, |key, value| {
+                keep(
+                    key.as_ptr(key_storage).get(),
+                    value.as_ptr(value_storage).get(),
+                )
+            })
+        }
+    }
+
+    /// Iterates over the entries in an unspecified order.
+    pub fn iter(&self) -> Iter<'_, 'a, K, V> {
+        Iter {
+            entries: self.hash_map.entries(),
+            key_storage: self.hash_map.storage().storage_for(K::SHAPE),
+            value_storage: self.hash_map.storage().storage_for(V::SHAPE),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Iterates over the entries with mutable references to the values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, 'a, K, V> {
+        let key_storage = self.hash_map.storage().storage_for(K::SHAPE);
+        let value_storage = self.hash_map.storage().storage_for(V::SHAPE);
+        IterMut {
+            entries: self.hash_map.entries_mut(),
+            key_storage,
+            value_storage,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    pub fn keys(&self) -> Keys<'_, 'a, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, 'a, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    pub fn values_mut(&mut self) -> ValuesMut<'_, 'a, K, V> {
+        ValuesMut {
+            inner: self.iter_mut(),
+        }
+    }
+
+    pub fn into_keys(self) -> IntoKeys<'a, K, V, B> {
+        IntoKeys {
+            entries: self.into_entries(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    pub fn into_values(self) -> IntoValues<'a, K, V, B> {
+        IntoValues {
+            entries: self.into_entries(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Hands the entries over to an owning iterator, which has to drop them.
+    fn into_entries(self) -> ErasedIntoIter<B> {
+        let this = std::mem::ManuallyDrop::new(self);
+        let hash_map = unsafe { std::ptr::read(&this.hash_map) };
+        hash_map.into_entries()
+    }
+}
+
+impl<'m, 'a, K, V, S, B> IntoIterator for &'m FacetHashMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    type Item = (&'m K, &'m V);
+    type IntoIter = Iter<'m, 'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'m, 'a, K, V, S, B> IntoIterator for &'m mut FacetHashMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    type Item = (&'m K, &'m mut V);
+    type IntoIter = IterMut<'m, 'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<'a, K, V, S, B> IntoIterator for FacetHashMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    type Item = (K, V);
+    type IntoIter = IntoIter<'a, K, V, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            entries: self.into_entries(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+/// Undoes a [`FacetHashMap::scoped_insert`] when dropped.
+pub struct ScopedInsert<'m, 'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S: BuildHasher,
+    B: StorageBackend,
+{
+    map: &'m mut FacetHashMap<'a, K, V, S, B>,
+    key: Option<K>,
+    displaced: Option<V>,
+}
+
+impl<'a, K, V, S, B> std::ops::Deref for ScopedInsert<'_, 'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S: BuildHasher,
+    B: StorageBackend,
+{
+    type Target = FacetHashMap<'a, K, V, S, B>;
+
+    fn deref(&self) -> &Self::Target {
+        self.map
+    }
+}
+
+impl<'a, K, V, S, B> std::ops::DerefMut for ScopedInsert<'_, 'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S: BuildHasher,
+    B: StorageBackend,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.map
+    }
+}
+
+impl<'a, K, V, S, B> Drop for ScopedInsert<'_, 'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S: BuildHasher,
+    B: StorageBackend,
+{
+    fn drop(&mut self) {
+        let key = self.key.take().unwrap();
+        match self.displaced.take() {
+            Some(displaced) => {
+                self.map.insert(key, displaced);
+            }
+            None => {
+                self.map.remove(&key);
+            }
+        }
+    }
+}
+
+/// Formats a value with its shape's debug fn, or as `<shape>` if it has none.
+struct ShapeDebug<'p>(PtrConst<'p>, &'static Shape<'static>);
+
+impl fmt::Debug for ShapeDebug<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.1.vtable.debug)() {
+            Some(debug) => unsafe { debug(self.0, f) },
+            None => write!(f, "<{}>", self.1),
+        }
+    }
+}
+
+fn render_cell(ptr: PtrConst<'_>, shape: &'static Shape<'static>) -> String {
+    match (shape.vtable.display)() {
+        Some(display) => ShapeDisplay(ptr, display).to_string(),
+        None => format!("{:?}", ShapeDebug(ptr, shape)),
+    }
+}
+
+/// Formats a value with its shape's display fn.
+struct ShapeDisplay<'p>(PtrConst<'p>, DisplayFn);
+
+impl fmt::Display for ShapeDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        unsafe { (self.1)(self.0, f) }
+    }
+}
+
+impl<'a, K1, K2, V, S, B> FacetHashMap<'a, (K1, K2), V, S, B>
+where
+    K1: Facet<'a> + Hash + Eq,
+    K2: Facet<'a> + Hash + Eq,
+    (K1, K2): Facet<'a>,
+    V: Facet<'a>,
+    S: BuildHasher,
+    B: StorageBackend,
+{
+    /// Looks up a pair key from references to its parts, without building
+    /// an owned tuple.
+    pub fn get_tuple(&self, key: (&K1, &K2)) -> Option<&V> {
+        let value_storage = self.hash_map.storage().storage_for(V::SHAPE);
+
+        unsafe {
+            self.hash_map
+                .find(&key, <(K1, K2)>::SHAPE, |candidate| {
+                    let (a, b) = candidate.get::<(K1, K2)>();
+                    a == key.0 && b == key.1
+                })
+                .map(|value| value.as_ptr(value_storage).get())
+        }
+    }
+
+    /// Iterates over the entries whose key starts with `first`. This scans
+    /// the whole map, comparing only the first component of each key.
+    pub fn iter_prefix<'m>(&'m self, first: &'m K1) -> impl Iterator<Item = (&'m (K1, K2), &'m V)> {
+        self.iter()
+            .filter(move |((key_first, _), _)| key_first == first)
+    }
+}
+
+impl<'a, K1, K2, V, S, B, S2, B2> FacetHashMap<'a, K1, FacetHashMap<'a, K2, V, S2, B2>, S, B>
+where
+    K1: Facet<'a>,
+    K2: Facet<'a>,
+    V: Facet<'a>,
+    S: BuildHasher + 'a,
+    B: StorageBackend + 'a,
+    S2: BuildHasher + 'a,
+    B2: StorageBackend + 'a,
+{
+    /// Looks up `k2` in the inner map for `k1`.
+    pub fn get2<Q1: Borrow<K1>, Q2: Borrow<K2>>(&self, k1: &Q1, k2: &Q2) -> Option<&V> {
+        self.get(k1)?.get(k2)
+    }
+
+    /// Inserts `value` under `k2` in the inner map for `k1`, creating an
+    /// empty inner map first if there isn't one.
+    pub fn insert2(&mut self, k1: K1, k2: K2, value: V) -> Option<V>
+    where
+        S2: Default,
+        B2: Default,
+    {
+        if let Some(inner) = self.get_mut(&k1) {
+            return inner.insert(k2, value);
+        }
+        let mut inner = FacetHashMap::default();
+        inner.insert(k2, value);
+        self.insert(k1, inner);
+        None
+    }
+
+    /// Moves the entries of `other` into this map, merging inner maps that
+    /// both have instead of replacing them. Where both inner maps have a
+    /// value, list values are combined according to `strategy`, a
+    /// [`FacetValue`] is merged like in [`crate::DynHashMap::deep_merge`],
+    /// and anything else is replaced.
+    pub fn deep_merge(&mut self, other: Self, strategy: MergeStrategy) {
+        for (k1, incoming_inner) in other {
+            let Some(inner) = self.get_mut(&k1) else {
+                self.insert(k1, incoming_inner);
+                continue;
+            };
+            for (k2, incoming) in incoming_inner {
+                match inner.get_mut(&k2) {
+                    Some(existing) => deep_merge_typed(existing, incoming, strategy),
+                    None => {
+                        inner.insert(k2, incoming);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K, V, S> FacetHashMap<'a, K, V, S, ArenaStorage>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+{
+    /// Drops every entry and reclaims the arena in one go, leaving the map
+    /// empty but ready to be refilled without new allocations.
+    pub fn reset(&mut self) {
+        self.clear();
+        self.hash_map.storage_mut().reset();
+    }
+}
+
+fn clone_fn<'a, T: Facet<'a>>() -> impl Fn(&T) -> T {
+    let clone_into = (T::SHAPE.vtable.clone_into)()
+        .unwrap_or_else(|| panic!("shape `{}` can't be cloned", T::SHAPE));
+    move |value| {
+        let mut clone = std::mem::MaybeUninit::<T>::uninit();
+        unsafe {
+            clone_into(PtrConst::new(value), PtrUninit::new(clone.as_mut_ptr()));
+            clone.assume_init()
         }
     }
 }
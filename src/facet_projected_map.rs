@@ -0,0 +1,109 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+
+use facet::{Facet, PtrConst};
+
+use crate::erased::Erased;
+use crate::erased_hashmap::{ErasedKeyRef, ErasedValue};
+use crate::erased_projected_map::{ErasedProjectedMap, KeyProjection, field_path_projection};
+use crate::storage::{DefaultStorage, StorageBackend};
+
+/// A map that stores only values, finding each value's key `K` inside the
+/// value itself, either through a facet field path or a closure.
+pub struct FacetProjectedMap<
+    'a,
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S = hashbrown::DefaultHashBuilder,
+    B: StorageBackend = DefaultStorage,
+> {
+    projected_map: ErasedProjectedMap<S, B>,
+    _marker: std::marker::PhantomData<(K, V, &'a ())>,
+}
+
+impl<'a, K, V, S, B> Drop for FacetProjectedMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    fn drop(&mut self) {
+        unsafe {
+            ErasedProjectedMap::drop_values(&mut self.projected_map, V::SHAPE);
+        }
+    }
+}
+
+impl<'a, K, V, S, B> FacetProjectedMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    B: StorageBackend,
+{
+    /// Keys values by the field at the dotted `path`, e.g. `"meta.id"`.
+    /// Returns `None` if there is no such field or its shape isn't `K`'s.
+    pub fn with_field_path(path: &str) -> Option<Self>
+    where
+        S: Default,
+        B: Default,
+    {
+        let (project_key, key_shape) = field_path_projection(V::SHAPE, path)?;
+        if !key_shape.is_shape(K::SHAPE) {
+            return None;
+        }
+        Some(Self::with_projection(
+            project_key,
+            S::default(),
+            B::default(),
+        ))
+    }
+
+    /// Keys values by the result of `project_key`.
+    pub fn with_key_fn(project_key: impl Fn(&V) -> &K + 'static) -> Self
+    where
+        S: Default,
+        B: Default,
+    {
+        let project_key: KeyProjection = Box::new(move |value_ref| {
+            let value: &V = unsafe { value_ref.get() };
+            PtrConst::new(project_key(value))
+        });
+        Self::with_projection(project_key, S::default(), B::default())
+    }
+
+    fn with_projection(project_key: KeyProjection, hash_builder: S, storage: B) -> Self {
+        Self {
+            projected_map: ErasedProjectedMap::new(project_key, hash_builder, storage),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Inserts `value` under its projected key, returning the value it replaced.
+    pub fn insert(&mut self, value: V) -> Option<V>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        let erased_value = ErasedValue(Erased::new(value, self.projected_map.storage()));
+        let old_erased_value =
+            unsafe { self.projected_map.insert(erased_value, K::SHAPE, V::SHAPE) };
+
+        old_erased_value
+            .map(|old_value| unsafe { old_value.0.into_typed(self.projected_map.storage()) })
+    }
+
+    pub fn get<'b, Q: Borrow<K>>(&'b self, key: &Q) -> Option<&'b V>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        let key_ref = PtrConst::new(key.borrow());
+        let value_storage = self.projected_map.storage().storage_for(V::SHAPE);
+
+        unsafe {
+            self.projected_map
+                .get(ErasedKeyRef(key_ref), K::SHAPE, V::SHAPE)
+                .map(|value| value.0.as_ptr(value_storage).get())
+        }
+    }
+}
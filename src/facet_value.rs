@@ -0,0 +1,197 @@
+use std::fmt;
+
+use facet::{
+    Def, Facet, ParseError, PtrConst, PtrMut, ScalarAffinity, Shape, Type, UserType, ValueVTable,
+    value_vtable,
+};
+
+use crate::erased::{Erased, ErasedStorage};
+use crate::storage::{DefaultStorage, StorageBackend};
+
+/// An owned value of any facet shape, for maps whose values are only known
+/// at runtime. It can be converted back into the typed value with
+/// [`FacetValue::downcast`].
+pub struct FacetValue {
+    value: Erased,
+    shape: &'static Shape<'static>,
+}
+
+/// A JSON-like classification of a [`FacetValue`]'s shape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FacetValueKind {
+    Null,
+    String,
+    Scalar,
+    List,
+    Map,
+    Other,
+}
+
+impl FacetValue {
+    /// Passing a `FacetValue` returns it as is rather than nesting it.
+    pub fn new<T: Facet<'static>>(value: T) -> Self {
+        if T::SHAPE.is_shape(Self::SHAPE) {
+            let value = std::mem::ManuallyDrop::new(value);
+            return unsafe { std::ptr::read(&*value as *const T as *const Self) };
+        }
+
+        Self {
+            value: Erased::new(value, &DefaultStorage),
+            shape: T::SHAPE,
+        }
+    }
+
+    /// The JSON-like null, represented as a `()` value.
+    pub fn null() -> Self {
+        Self::new(())
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.is::<()>()
+    }
+
+    pub fn shape(&self) -> &'static Shape<'static> {
+        self.shape
+    }
+
+    pub fn kind(&self) -> FacetValueKind {
+        match self.shape.def {
+            Def::Scalar(scalar_def) => match scalar_def.affinity {
+                ScalarAffinity::Empty(_) => FacetValueKind::Null,
+                ScalarAffinity::String(_) => FacetValueKind::String,
+                _ => FacetValueKind::Scalar,
+            },
+            Def::List(_) | Def::Array(_) | Def::Slice(_) => FacetValueKind::List,
+            Def::Map(_) => FacetValueKind::Map,
+            _ => FacetValueKind::Other,
+        }
+    }
+
+    pub(crate) fn storage(&self) -> ErasedStorage {
+        DefaultStorage.storage_for(self.shape)
+    }
+
+    /// A pointer to the value, which has [`FacetValue::shape`].
+    pub fn as_ptr(&self) -> PtrConst<'_> {
+        unsafe { self.value.as_ptr(self.storage()) }
+    }
+
+    pub fn as_mut_ptr(&mut self) -> PtrMut<'_> {
+        let storage = self.storage();
+        unsafe { self.value.as_mut_ptr(storage) }
+    }
+
+    pub fn is<T: Facet<'static>>(&self) -> bool {
+        self.shape.is_shape(T::SHAPE)
+    }
+
+    pub fn downcast_ref<T: Facet<'static>>(&self) -> Option<&T> {
+        self.is::<T>().then(|| unsafe { self.as_ptr().get() })
+    }
+
+    pub fn downcast_mut<T: Facet<'static>>(&mut self) -> Option<&mut T> {
+        self.is::<T>()
+            .then(|| unsafe { self.as_mut_ptr().as_mut() })
+    }
+
+    /// Converts back into the typed value, or returns `self` if it isn't a `T`.
+    pub fn downcast<T: Facet<'static>>(self) -> Result<T, Self> {
+        if !self.is::<T>() {
+            return Err(self);
+        }
+        let this = std::mem::ManuallyDrop::new(self);
+        let value = unsafe { std::ptr::read(&this.value) };
+        Ok(unsafe { value.into_typed(&DefaultStorage) })
+    }
+
+    /// Parses `s` into a value of `shape` using the shape's `parse` fn.
+    pub fn parse(s: &str, shape: &'static Shape<'static>) -> Result<Self, ParseError> {
+        let parse = (shape.vtable.parse)().ok_or(ParseError::Generic("shape has no parse fn"))?;
+        let value = unsafe {
+            Erased::try_from_init(shape, &DefaultStorage, |target| {
+                parse(s, target).map(|_| ())
+            })?
+        };
+        Ok(Self { value, shape })
+    }
+
+    /// Hands a pointer to the value to `move_out`, which must move the value
+    /// out (e.g. with a vtable fn that takes ownership), then frees the
+    /// storage without dropping it.
+    pub(crate) unsafe fn move_out(self, move_out: impl FnOnce(PtrMut<'_>)) {
+        let mut this = std::mem::ManuallyDrop::new(self);
+        move_out(this.as_mut_ptr());
+        unsafe { std::ptr::read(&this.value).forget(this.shape, &DefaultStorage) };
+    }
+
+    /// Clones the value through its shape, if the shape supports it.
+    pub fn try_clone(&self) -> Option<Self> {
+        unsafe { Self::clone_from_ptr(self.as_ptr(), self.shape) }
+    }
+
+    /// Clones the value at `ptr` through `shape`'s `clone_into`, if it has one.
+    ///
+    /// # Safety
+    /// `ptr` must point to a value of `shape`
+    pub unsafe fn clone_from_ptr(
+        ptr: PtrConst<'_>,
+        shape: &'static Shape<'static>,
+    ) -> Option<Self> {
+        let clone_into = (shape.vtable.clone_into)()?;
+        let value = unsafe {
+            Erased::from_init(shape, &DefaultStorage, |target| {
+                clone_into(ptr, target);
+            })
+        };
+        Some(Self { value, shape })
+    }
+}
+
+impl Drop for FacetValue {
+    fn drop(&mut self) {
+        if let Some(drop_value) = Erased::drop_fn(self.shape, &DefaultStorage) {
+            drop_value(&mut self.value);
+        }
+    }
+}
+
+impl fmt::Debug for FacetValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.shape.vtable.debug)() {
+            Some(debug) => unsafe { debug(self.as_ptr(), f) },
+            None => write!(f, "<{}>", self.shape),
+        }
+    }
+}
+
+/// Values are equal if they have the same shape and the shape's
+/// `partial_eq` says so.
+impl PartialEq for FacetValue {
+    fn eq(&self, other: &Self) -> bool {
+        if !self.shape.is_shape(other.shape) {
+            return false;
+        }
+        match (self.shape.vtable.partial_eq)() {
+            Some(eq) => unsafe { eq(self.as_ptr(), other.as_ptr()) },
+            None => false,
+        }
+    }
+}
+
+unsafe impl Facet<'static> for FacetValue {
+    const VTABLE: &'static ValueVTable = &const {
+        value_vtable!(FacetValue, |f, _opts| write!(
+            f,
+            "{}",
+            Self::SHAPE.type_identifier
+        ))
+    };
+
+    const SHAPE: &'static Shape<'static> = &const {
+        Shape::builder_for_sized::<Self>()
+            .type_identifier("FacetValue")
+            .ty(Type::User(UserType::Opaque))
+            .def(Def::Undefined)
+            .build()
+    };
+}
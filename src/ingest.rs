@@ -0,0 +1,211 @@
+use std::fmt;
+use std::hash::BuildHasher;
+use std::mem::MaybeUninit;
+
+use facet::{Facet, ParseError, PtrUninit, Shape, Type, UserType};
+
+use crate::dyn_map::DynHashMap;
+use crate::erased_hashmap::enum_tag_size;
+use crate::facet_hashmap::FacetHashMap;
+use crate::facet_value::FacetValue;
+
+/// The string value for `key` couldn't be parsed into `shape`.
+#[derive(Debug)]
+pub struct ParseValueError {
+    pub key: String,
+    pub shape: &'static Shape<'static>,
+    pub error: ParseError,
+}
+
+impl fmt::Display for ParseValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "can't parse the value for {:?} as {}: {}",
+            self.key, self.shape, self.error
+        )
+    }
+}
+
+impl std::error::Error for ParseValueError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The string `key` couldn't be parsed into the key shape `shape`.
+#[derive(Debug)]
+pub struct ParseKeyError {
+    pub key: String,
+    pub shape: &'static Shape<'static>,
+    pub error: ParseError,
+}
+
+impl fmt::Display for ParseKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "can't parse key {:?} as {}: {}",
+            self.key, self.shape, self.error
+        )
+    }
+}
+
+impl std::error::Error for ParseKeyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl<S: BuildHasher + Default> FacetHashMap<'static, String, String, S> {
+    /// Collects the environment variables whose names start with `prefix`,
+    /// keyed by the rest of the name in lowercase: with prefix `"APP_"`,
+    /// `APP_SERVER_PORT=8080` becomes `"server_port" => "8080"`.
+    pub fn from_env(prefix: &str) -> Self {
+        Self::from_vars(prefix, std::env::vars())
+    }
+
+    /// Like [`Self::from_env`], but reads the given variables instead.
+    pub fn from_vars(prefix: &str, vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut map = Self::default();
+        for (name, value) in vars {
+            if let Some(key) = name.strip_prefix(prefix) {
+                map.insert(key.to_lowercase(), value);
+            }
+        }
+        map
+    }
+
+    /// Collects `--key=value` arguments. A bare `--flag` is stored as
+    /// `"true"`, and arguments not starting with `--` are skipped.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Self {
+        let mut map = Self::default();
+        for arg in args {
+            let Some(arg) = arg.strip_prefix("--") else {
+                continue;
+            };
+            match arg.split_once('=') {
+                Some((key, value)) => map.insert(key.to_string(), value.to_string()),
+                None => map.insert(arg.to_string(), "true".to_string()),
+            };
+        }
+        map
+    }
+}
+
+impl<S: BuildHasher> FacetHashMap<'static, String, String, S> {
+    /// Parses every value into a `V` with the shape's `parse` fn.
+    pub fn parse_values<V, S2>(
+        &self,
+    ) -> Result<FacetHashMap<'static, String, V, S2>, ParseValueError>
+    where
+        V: Facet<'static>,
+        S2: BuildHasher + Default,
+    {
+        let mut map = FacetHashMap::default();
+        for (key, value) in self.iter() {
+            let value = parse_typed(value).map_err(|error| ParseValueError {
+                key: key.clone(),
+                shape: V::SHAPE,
+                error,
+            })?;
+            map.insert(key.clone(), value);
+        }
+        Ok(map)
+    }
+
+    /// Parses each value into the shape of the field of the struct `shape`
+    /// with the same name, ready for [`DynHashMap::to_struct`]. Values
+    /// without a matching field are kept as strings.
+    pub fn parse_fields<S2>(
+        &self,
+        shape: &'static Shape<'static>,
+    ) -> Result<DynHashMap<S2>, ParseValueError>
+    where
+        S2: BuildHasher + Default,
+    {
+        let fields = match shape.ty {
+            Type::User(UserType::Struct(struct_type)) => struct_type.fields,
+            _ => &[],
+        };
+
+        let mut map = DynHashMap::default();
+        for (key, value) in self.iter() {
+            let value = match fields.iter().find(|field| field.name == key) {
+                Some(field) => {
+                    FacetValue::parse(value, field.shape()).map_err(|error| ParseValueError {
+                        key: key.clone(),
+                        shape: field.shape(),
+                        error,
+                    })?
+                }
+                None => FacetValue::new(value.clone()),
+            };
+            map.insert(key.clone(), value);
+        }
+        Ok(map)
+    }
+}
+
+impl<V, S> FacetHashMap<'static, String, V, S>
+where
+    V: Facet<'static> + Clone,
+    S: BuildHasher,
+{
+    /// Parses every key into a `K` with the shape's `parse` fn, e.g. the
+    /// keys of a JSON object into integers or enum variant names.
+    pub fn parse_keys<K, S2>(&self) -> Result<FacetHashMap<'static, K, V, S2>, ParseKeyError>
+    where
+        K: Facet<'static>,
+        S2: BuildHasher + Default,
+    {
+        let mut map = FacetHashMap::default();
+        for (key, value) in self.iter() {
+            let parsed_key = parse_typed(key).map_err(|error| ParseKeyError {
+                key: key.clone(),
+                shape: K::SHAPE,
+                error,
+            })?;
+            map.insert(parsed_key, value.clone());
+        }
+        Ok(map)
+    }
+}
+
+fn parse_typed<T: Facet<'static>>(s: &str) -> Result<T, ParseError> {
+    let mut value = MaybeUninit::<T>::uninit();
+    unsafe {
+        parse_into(s, T::SHAPE, PtrUninit::new(value.as_mut_ptr()))?;
+        Ok(value.assume_init())
+    }
+}
+
+/// Uses the shape's `parse` fn, or for enums without one, matches the names
+/// of the unit variants.
+/// Safety: `target` must be valid for writing a value of `shape`.
+unsafe fn parse_into(s: &str, shape: &Shape, target: PtrUninit<'_>) -> Result<(), ParseError> {
+    if let Some(parse) = (shape.vtable.parse)() {
+        return unsafe { parse(s, target).map(|_| ()) };
+    }
+
+    let (Type::User(UserType::Enum(enum_type)), Some(tag_size)) = (shape.ty, enum_tag_size(shape))
+    else {
+        return Err(ParseError::Generic("shape has no parse fn"));
+    };
+    let discriminant = enum_type
+        .variants
+        .iter()
+        .find(|variant| variant.name == s && variant.data.fields.is_empty())
+        .and_then(|variant| variant.discriminant)
+        .ok_or(ParseError::Generic("no unit variant with that name"))?;
+
+    unsafe {
+        match tag_size {
+            1 => target.put(discriminant as u8),
+            2 => target.put(discriminant as u16),
+            4 => target.put(discriminant as u32),
+            _ => target.put(discriminant as u64),
+        };
+    }
+    Ok(())
+}
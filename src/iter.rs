@@ -0,0 +1,269 @@
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+
+use facet::Facet;
+
+use crate::erased::{Erased, ErasedStorage};
+use crate::erased_hashmap::{ErasedIntoIter, ErasedIter, ErasedIterMut};
+use crate::storage::{DefaultStorage, StorageBackend};
+
+/// An iterator over the entries of a [`crate::FacetHashMap`], from
+/// [`crate::FacetHashMap::iter`].
+pub struct Iter<'m, 'a, K: Facet<'a>, V: Facet<'a>> {
+    pub(crate) entries: ErasedIter<'m>,
+    pub(crate) key_storage: ErasedStorage,
+    pub(crate) value_storage: ErasedStorage,
+    pub(crate) _marker: PhantomData<(&'m K, &'m V, &'a ())>,
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>> Clone for Iter<'_, 'a, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+            ..*self
+        }
+    }
+}
+
+impl<'m, 'a, K: Facet<'a>, V: Facet<'a>> Iterator for Iter<'m, 'a, K, V> {
+    type Item = (&'m K, &'m V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.entries.next()?;
+        unsafe {
+            Some((
+                key.as_ptr(self.key_storage).get(),
+                value.as_ptr(self.value_storage).get(),
+            ))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>> ExactSizeIterator for Iter<'_, 'a, K, V> {}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>> FusedIterator for Iter<'_, 'a, K, V> {}
+
+/// A mutable iterator over the entries of a [`crate::FacetHashMap`], from
+/// [`crate::FacetHashMap::iter_mut`].
+pub struct IterMut<'m, 'a, K: Facet<'a>, V: Facet<'a>> {
+    pub(crate) entries: ErasedIterMut<'m>,
+    pub(crate) key_storage: ErasedStorage,
+    pub(crate) value_storage: ErasedStorage,
+    pub(crate) _marker: PhantomData<(&'m K, &'m mut V, &'a ())>,
+}
+
+impl<'m, 'a, K: Facet<'a>, V: Facet<'a>> Iterator for IterMut<'m, 'a, K, V> {
+    type Item = (&'m K, &'m mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.entries.next()?;
+        unsafe {
+            Some((
+                key.as_ptr(self.key_storage).get(),
+                value.as_mut_ptr(self.value_storage).as_mut(),
+            ))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>> ExactSizeIterator for IterMut<'_, 'a, K, V> {}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>> FusedIterator for IterMut<'_, 'a, K, V> {}
+
+/// An iterator that moves the entries out of a [`crate::FacetHashMap`].
+/// Entries that aren't taken are dropped along with it.
+pub struct IntoIter<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend = DefaultStorage> {
+    pub(crate) entries: ErasedIntoIter<B>,
+    pub(crate) _marker: PhantomData<(K, V, &'a ())>,
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend> Iterator for IntoIter<'a, K, V, B> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.entries.next()?;
+        let storage = self.entries.storage();
+        unsafe { Some((key.0.into_typed(storage), value.0.into_typed(storage))) }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend> ExactSizeIterator
+    for IntoIter<'a, K, V, B>
+{
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend> FusedIterator for IntoIter<'a, K, V, B> {}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend> Drop for IntoIter<'a, K, V, B> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+/// An iterator over the keys of a [`crate::FacetHashMap`], from
+/// [`crate::FacetHashMap::keys`].
+pub struct Keys<'m, 'a, K: Facet<'a>, V: Facet<'a>> {
+    pub(crate) inner: Iter<'m, 'a, K, V>,
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>> Clone for Keys<'_, 'a, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'m, 'a, K: Facet<'a>, V: Facet<'a>> Iterator for Keys<'m, 'a, K, V> {
+    type Item = &'m K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>> ExactSizeIterator for Keys<'_, 'a, K, V> {}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>> FusedIterator for Keys<'_, 'a, K, V> {}
+
+/// An iterator over the values of a [`crate::FacetHashMap`], from
+/// [`crate::FacetHashMap::values`].
+pub struct Values<'m, 'a, K: Facet<'a>, V: Facet<'a>> {
+    pub(crate) inner: Iter<'m, 'a, K, V>,
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>> Clone for Values<'_, 'a, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'m, 'a, K: Facet<'a>, V: Facet<'a>> Iterator for Values<'m, 'a, K, V> {
+    type Item = &'m V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>> ExactSizeIterator for Values<'_, 'a, K, V> {}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>> FusedIterator for Values<'_, 'a, K, V> {}
+
+/// A mutable iterator over the values of a [`crate::FacetHashMap`], from
+/// [`crate::FacetHashMap::values_mut`].
+pub struct ValuesMut<'m, 'a, K: Facet<'a>, V: Facet<'a>> {
+    pub(crate) inner: IterMut<'m, 'a, K, V>,
+}
+
+impl<'m, 'a, K: Facet<'a>, V: Facet<'a>> Iterator for ValuesMut<'m, 'a, K, V> {
+    type Item = &'m mut V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>> ExactSizeIterator for ValuesMut<'_, 'a, K, V> {}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>> FusedIterator for ValuesMut<'_, 'a, K, V> {}
+
+/// An iterator that moves the keys out of a [`crate::FacetHashMap`], from
+/// [`crate::FacetHashMap::into_keys`]. Each value is dropped as its key is taken.
+pub struct IntoKeys<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend = DefaultStorage> {
+    pub(crate) entries: ErasedIntoIter<B>,
+    pub(crate) _marker: PhantomData<(K, V, &'a ())>,
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend> Iterator for IntoKeys<'a, K, V, B> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, mut value) = self.entries.next()?;
+        let storage = self.entries.storage();
+        if let Some(drop_value) = Erased::drop_fn(V::SHAPE, storage) {
+            drop_value(&mut value.0);
+        }
+        unsafe { Some(key.0.into_typed(storage)) }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend> ExactSizeIterator
+    for IntoKeys<'a, K, V, B>
+{
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend> FusedIterator for IntoKeys<'a, K, V, B> {}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend> Drop for IntoKeys<'a, K, V, B> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+/// An iterator that moves the values out of a [`crate::FacetHashMap`], from
+/// [`crate::FacetHashMap::into_values`]. Each key is dropped as its value is taken.
+pub struct IntoValues<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend = DefaultStorage> {
+    pub(crate) entries: ErasedIntoIter<B>,
+    pub(crate) _marker: PhantomData<(K, V, &'a ())>,
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend> Iterator for IntoValues<'a, K, V, B> {
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (mut key, value) = self.entries.next()?;
+        let storage = self.entries.storage();
+        if let Some(drop_key) = Erased::drop_fn(K::SHAPE, storage) {
+            drop_key(&mut key.0);
+        }
+        unsafe { Some(value.0.into_typed(storage)) }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend> ExactSizeIterator
+    for IntoValues<'a, K, V, B>
+{
+}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend> FusedIterator for IntoValues<'a, K, V, B> {}
+
+impl<'a, K: Facet<'a>, V: Facet<'a>, B: StorageBackend> Drop for IntoValues<'a, K, V, B> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
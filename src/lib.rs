@@ -1,8 +1,56 @@
+mod any_shape_map;
+mod by_address;
+mod dyn_key_map;
+mod dyn_map;
+mod dyn_value_map;
+mod entry;
+mod epoch_map;
 mod erased;
+mod erased_alias_map;
 mod erased_hashmap;
+mod erased_projected_map;
+mod facet_alias_map;
 mod facet_hashmap;
+mod facet_projected_map;
+mod facet_value;
+mod ingest;
+mod iter;
+mod lww_map;
+mod maintenance;
+mod storage;
+mod total_float;
+mod watched_map;
 
-pub use facet_hashmap::FacetHashMap;
+pub use any_shape_map::AnyShapeMap;
+pub use by_address::ByAddress;
+pub use dyn_key_map::FacetDynKeyMap;
+pub use dyn_map::{
+    BuildError, CoerceError, DynHashMap, IncrementError, MergeStrategy, NestedMap, ValidationError,
+};
+pub use dyn_value_map::FacetDynValueMap;
+pub use entry::{Entry, EntryRef, OccupiedEntry, VacantEntry, VacantEntryRef};
+pub use epoch_map::{EpochEntry, EpochFacetMap};
+pub use erased::ErasedStorage;
+#[cfg(feature = "entry-timestamps")]
+pub use erased_hashmap::EntryMeta;
+pub use facet_alias_map::FacetAliasMap;
+pub use facet_hashmap::{ChangeSet, DuplicateKeys, FacetHashMap, ScopedInsert};
+pub use facet_projected_map::FacetProjectedMap;
+pub use facet_value::{FacetValue, FacetValueKind};
+pub use ingest::{ParseKeyError, ParseValueError};
+pub use iter::{IntoIter, IntoKeys, IntoValues, Iter, IterMut, Keys, Values, ValuesMut};
+pub use lww_map::{LwwEntry, LwwFacetMap, LwwTag};
+pub use maintenance::Maintenance;
+#[cfg(feature = "background-maintenance")]
+pub use maintenance::spawn_maintenance;
+pub use storage::{
+    AllocHooks, ArenaStorage, BoxedStorage, DefaultStorage, HookedStorage, PooledStorage,
+    StorageBackend, StorageReport,
+};
+#[cfg(feature = "leak-check")]
+pub use storage::{LeakCheckStorage, LeakCounter};
+pub use total_float::TotalFloat;
+pub use watched_map::{WatchEvent, WatchedFacetMap};
 
 #[test]
 fn test_facet_hashmap() {
@@ -14,3 +62,2199 @@ fn test_facet_hashmap() {
     assert_eq!(facet_hashmap.get(&"key2"), Some(&"value2"));
     assert_eq!(facet_hashmap.get(&"key3"), None);
 }
+
+#[test]
+fn test_storage_backends() {
+    fn exercise<B: StorageBackend>(storage: B) {
+        let mut facet_hashmap = FacetHashMap::<String, String, _, B>::with_hasher_and_storage(
+            hashbrown::DefaultHashBuilder::default(),
+            storage,
+        );
+        for i in 0..100 {
+            facet_hashmap.insert(format!("key{i}"), format!("value{i}"));
+        }
+        assert_eq!(
+            facet_hashmap.insert("key7".to_string(), "seven".to_string()),
+            Some("value7".to_string())
+        );
+
+        assert_eq!(
+            facet_hashmap.get(&"key7".to_string()),
+            Some(&"seven".to_string())
+        );
+        assert_eq!(
+            facet_hashmap.get(&"key42".to_string()),
+            Some(&"value42".to_string())
+        );
+        assert_eq!(facet_hashmap.get(&"key100".to_string()), None);
+    }
+
+    exercise(DefaultStorage);
+    exercise(ArenaStorage::default());
+    exercise(PooledStorage::default());
+}
+
+#[test]
+fn test_arena_reset() {
+    let mut facet_hashmap =
+        FacetHashMap::<String, String, hashbrown::DefaultHashBuilder, ArenaStorage>::default();
+    for frame in 0..3 {
+        for i in 0..100 {
+            facet_hashmap.insert(format!("key{i}"), format!("frame{frame}"));
+        }
+        assert_eq!(
+            facet_hashmap.get(&"key99".to_string()),
+            Some(&format!("frame{frame}"))
+        );
+        facet_hashmap.reset();
+        assert_eq!(facet_hashmap.get(&"key99".to_string()), None);
+    }
+}
+
+#[test]
+fn test_unhashable_keys_fall_back_to_sorted_entries() {
+    use facet::Facet;
+
+    #[derive(Facet, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    struct Version {
+        major: u32,
+        minor: u32,
+    }
+
+    let mut facet_hashmap = FacetHashMap::<Version, &str>::default();
+    for (major, minor, name) in [(1, 2, "b"), (0, 9, "a"), (2, 0, "c")] {
+        facet_hashmap.insert(Version { major, minor }, name);
+    }
+    assert_eq!(
+        facet_hashmap.insert(Version { major: 1, minor: 2 }, "B"),
+        Some("b")
+    );
+    assert_eq!(facet_hashmap.len(), 3);
+
+    assert_eq!(
+        facet_hashmap.get(&Version { major: 0, minor: 9 }),
+        Some(&"a")
+    );
+    assert_eq!(
+        facet_hashmap.get(&Version { major: 1, minor: 2 }),
+        Some(&"B")
+    );
+    assert_eq!(facet_hashmap.get(&Version { major: 1, minor: 3 }), None);
+}
+
+#[test]
+fn test_facet_projected_map() {
+    use facet::Facet;
+
+    #[derive(Facet, Debug, PartialEq)]
+    struct Meta {
+        id: u64,
+    }
+
+    #[derive(Facet, Debug, PartialEq)]
+    struct User {
+        meta: Meta,
+        name: String,
+    }
+
+    let user = |id, name: &str| User {
+        meta: Meta { id },
+        name: name.to_string(),
+    };
+
+    let mut by_path = FacetProjectedMap::<u64, User>::with_field_path("meta.id").unwrap();
+    by_path.insert(user(1, "alice"));
+    by_path.insert(user(2, "bob"));
+    assert_eq!(by_path.insert(user(1, "alicia")), Some(user(1, "alice")));
+    assert_eq!(by_path.get(&1), Some(&user(1, "alicia")));
+    assert_eq!(by_path.get(&3), None);
+
+    assert!(FacetProjectedMap::<u64, User>::with_field_path("meta.missing").is_none());
+    assert!(FacetProjectedMap::<u64, User>::with_field_path("name").is_none());
+
+    let mut by_fn = FacetProjectedMap::<String, User>::with_key_fn(|user| &user.name);
+    by_fn.insert(user(1, "alice"));
+    assert_eq!(by_fn.get(&"alice".to_string()), Some(&user(1, "alice")));
+}
+
+#[test]
+fn test_facet_alias_map() {
+    let mut aliases = FacetAliasMap::<String, String>::default();
+    aliases.insert("example.com".to_string(), "93.184.216.34".to_string());
+    aliases.insert("other.org".to_string(), "10.0.0.1".to_string());
+    aliases
+        .add_alias(&"example.com".to_string(), "www.example.com".to_string())
+        .unwrap();
+    assert_eq!(
+        aliases.add_alias(&"missing.net".to_string(), "alias.net".to_string()),
+        Err("alias.net".to_string())
+    );
+
+    assert_eq!(aliases.alias_count(&"www.example.com".to_string()), 2);
+    assert_eq!(
+        aliases.insert("www.example.com".to_string(), "93.184.216.35".to_string()),
+        Some("93.184.216.34".to_string())
+    );
+    assert_eq!(
+        aliases.get(&"example.com".to_string()),
+        Some(&"93.184.216.35".to_string())
+    );
+
+    assert_eq!(
+        aliases.remove(&"example.com".to_string()),
+        Some("93.184.216.35".to_string())
+    );
+    assert_eq!(aliases.get(&"www.example.com".to_string()), None);
+    assert_eq!(aliases.alias_count(&"example.com".to_string()), 0);
+    assert_eq!(
+        aliases.get(&"other.org".to_string()),
+        Some(&"10.0.0.1".to_string())
+    );
+}
+
+#[test]
+fn test_facet_value() {
+    let mut settings = FacetHashMap::<&str, FacetValue>::default();
+    settings.insert("name", FacetValue::new("server".to_string()));
+    settings.insert("port", FacetValue::new(8080u16));
+    settings.insert(
+        "hosts",
+        FacetValue::new(vec!["a".to_string(), "b".to_string()]),
+    );
+
+    let port = settings.get(&"port").unwrap();
+    assert_eq!(port.kind(), FacetValueKind::Scalar);
+    assert_eq!(port.downcast_ref::<u16>(), Some(&8080));
+    assert_eq!(port.downcast_ref::<u32>(), None);
+    assert_eq!(format!("{port:?}"), "8080");
+
+    let hosts = settings.get(&"hosts").unwrap().try_clone().unwrap();
+    assert_eq!(hosts.kind(), FacetValueKind::List);
+    assert_eq!(&hosts, settings.get(&"hosts").unwrap());
+    assert_eq!(
+        hosts.downcast::<Vec<String>>().unwrap(),
+        vec!["a".to_string(), "b".to_string()]
+    );
+
+    let name = settings.get(&"name").unwrap().try_clone().unwrap();
+    assert_eq!(name.kind(), FacetValueKind::String);
+    let name = name.downcast::<u16>().unwrap_err();
+    assert_eq!(name.downcast::<String>().unwrap(), "server");
+
+    let nested = FacetValue::new(std::collections::HashMap::<String, FacetValue>::new());
+    assert_eq!(nested.kind(), FacetValueKind::Map);
+}
+
+#[test]
+fn test_dyn_hashmap_paths() {
+    use facet::Facet;
+
+    #[derive(Facet, Debug, PartialEq)]
+    struct Tls {
+        cert: String,
+        enabled: bool,
+    }
+
+    let mut config: DynHashMap = DynHashMap::default();
+    config.set_path("server.port", 8080u16).unwrap();
+    config
+        .set_path("server.host", "localhost".to_string())
+        .unwrap();
+    config
+        .set_path(
+            "server.tls",
+            Tls {
+                cert: "a.pem".to_string(),
+                enabled: false,
+            },
+        )
+        .unwrap();
+    config.set_path("debug", true).unwrap();
+
+    assert_eq!(config.get_path::<u16>("server.port"), Some(&8080));
+    assert_eq!(config.get_path::<u32>("server.port"), None);
+    assert_eq!(config.get_path::<bool>("debug"), Some(&true));
+    assert_eq!(
+        config.get_path::<String>("server.tls.cert"),
+        Some(&"a.pem".to_string())
+    );
+    assert_eq!(config.get_path::<String>("server.missing"), None);
+
+    config.set_path("server.tls.enabled", true).unwrap();
+    assert_eq!(config.set_path("server.tls.enabled", 1u8), Err(1));
+    assert_eq!(config.set_path("server.port.inner", 1u8), Err(1));
+    *config.get_path_mut::<u16>("server.port").unwrap() += 1;
+
+    assert_eq!(config.get_path::<bool>("server.tls.enabled"), Some(&true));
+    assert_eq!(config.get_path::<u16>("server.port"), Some(&8081));
+}
+
+#[test]
+fn test_dyn_hashmap_merge_patch() {
+    let mut config: DynHashMap = DynHashMap::default();
+    config.set_path("title", "Goodbye!".to_string()).unwrap();
+    config
+        .set_path("author.givenName", "John".to_string())
+        .unwrap();
+    config
+        .set_path("author.familyName", "Doe".to_string())
+        .unwrap();
+    config
+        .set_path("tags", vec!["example".to_string()])
+        .unwrap();
+
+    let mut patch: DynHashMap = DynHashMap::default();
+    patch.set_path("title", "Hello!".to_string()).unwrap();
+    patch
+        .set_path("phoneNumber", "+01-123-456-7890".to_string())
+        .unwrap();
+    patch
+        .set_path("author.familyName", FacetValue::null())
+        .unwrap();
+    patch.set_path("tags", vec!["sample".to_string()]).unwrap();
+    patch.set_path("missing", FacetValue::null()).unwrap();
+
+    config.merge_patch(&patch);
+
+    assert_eq!(
+        config.get_path::<String>("title"),
+        Some(&"Hello!".to_string())
+    );
+    assert_eq!(
+        config.get_path::<String>("author.givenName"),
+        Some(&"John".to_string())
+    );
+    assert_eq!(config.get_path::<FacetValue>("author.familyName"), None);
+    assert_eq!(
+        config.get_path::<String>("phoneNumber"),
+        Some(&"+01-123-456-7890".to_string())
+    );
+    assert_eq!(
+        config.get_path::<Vec<String>>("tags"),
+        Some(&vec!["sample".to_string()])
+    );
+    assert_eq!(config.get_path::<FacetValue>("missing"), None);
+
+    let mut server: DynHashMap = DynHashMap::default();
+    server.insert("port".to_string(), FacetValue::new(8080u16));
+    server.insert("debug".to_string(), FacetValue::new(true));
+    config.insert("server".to_string(), FacetValue::new(server));
+    let mut server_patch: DynHashMap = DynHashMap::default();
+    server_patch.insert("port".to_string(), FacetValue::new(9090u16));
+    server_patch.insert("debug".to_string(), FacetValue::null());
+    let mut patch: DynHashMap = DynHashMap::default();
+    patch.insert("server".to_string(), FacetValue::new(server_patch));
+    let mut author_patch: DynHashMap = DynHashMap::default();
+    author_patch.insert("familyName".to_string(), FacetValue::new("Roe".to_string()));
+    patch.insert("author".to_string(), FacetValue::new(author_patch));
+
+    config.merge_patch(&patch);
+    let server = config
+        .get(&"server".to_string())
+        .and_then(FacetValue::downcast_ref::<DynHashMap>)
+        .unwrap();
+    assert_eq!(server.get_path::<u16>("port"), Some(&9090));
+    assert!(server.get(&"debug".to_string()).is_none());
+    assert_eq!(
+        config.get_path::<String>("author.familyName"),
+        Some(&"Roe".to_string())
+    );
+    assert_eq!(
+        config.get_path::<String>("author.givenName"),
+        Some(&"John".to_string())
+    );
+}
+
+#[test]
+fn test_dyn_hashmap_validate_against() {
+    use facet::Facet;
+
+    #[derive(Facet)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    #[derive(Facet)]
+    struct Config {
+        server: Server,
+        name: String,
+        #[facet(default)]
+        debug: bool,
+        comment: Option<String>,
+    }
+
+    let mut config: DynHashMap = DynHashMap::default();
+    config
+        .set_path("server.host", "localhost".to_string())
+        .unwrap();
+    config.set_path("server.port", 8080u16).unwrap();
+    config.set_path("name", "app".to_string()).unwrap();
+    assert_eq!(config.validate_against(Config::SHAPE), Ok(()));
+
+    config.set_path("server.port", 8080u32).unwrap();
+    config.set_path("server.tls", true).unwrap();
+    config.remove(&"name".to_string());
+    let errors = config.validate_against(Config::SHAPE).unwrap_err();
+    assert_eq!(errors.len(), 3);
+    assert!(errors.contains(&ValidationError::MissingField {
+        field: "name".to_string()
+    }));
+    assert!(errors.contains(&ValidationError::UnknownField {
+        field: "server.tls".to_string()
+    }));
+    assert!(errors.contains(&ValidationError::MismatchedShape {
+        field: "server.port".to_string(),
+        expected: u16::SHAPE,
+        found: u32::SHAPE,
+    }));
+
+    assert_eq!(
+        config.validate_against(u16::SHAPE),
+        Err(vec![ValidationError::NotAStruct { shape: u16::SHAPE }])
+    );
+}
+
+#[test]
+fn test_dyn_hashmap_to_struct() {
+    use facet::Facet;
+
+    #[derive(Facet, Debug, PartialEq)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    #[derive(Facet, Debug, PartialEq)]
+    struct Config {
+        server: Server,
+        #[facet(default)]
+        workers: Vec<String>,
+        comment: Option<String>,
+        extra: FacetValue,
+    }
+
+    let mut config: DynHashMap = DynHashMap::default();
+    config
+        .set_path("server.host", "localhost".to_string())
+        .unwrap();
+    config.set_path("server.port", 8080u16).unwrap();
+    config.set_path("extra", 1.5f64).unwrap();
+    config.set_path("ignored", true).unwrap();
+
+    assert_eq!(
+        config.to_struct::<Config>(),
+        Ok(Config {
+            server: Server {
+                host: "localhost".to_string(),
+                port: 8080,
+            },
+            workers: Vec::new(),
+            comment: None,
+            extra: FacetValue::new(1.5f64),
+        })
+    );
+
+    config.remove(&"extra".to_string());
+    assert_eq!(
+        config.to_struct::<Config>(),
+        Err(BuildError::Invalid(ValidationError::MissingField {
+            field: "extra".to_string()
+        }))
+    );
+
+    config.set_path("extra", ()).unwrap();
+    config.set_path("server.port", "80".to_string()).unwrap();
+    assert_eq!(
+        config.to_struct::<Config>(),
+        Err(BuildError::Invalid(ValidationError::MismatchedShape {
+            field: "server.port".to_string(),
+            expected: u16::SHAPE,
+            found: String::SHAPE,
+        }))
+    );
+    assert_eq!(
+        config.to_struct::<Config>().unwrap_err().to_string(),
+        "field `server.port` should be u16, found String"
+    );
+}
+
+#[test]
+fn test_dyn_hashmap_from_struct() {
+    use facet::Facet;
+
+    #[derive(Facet, Debug, PartialEq, Clone)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    #[derive(Facet, Debug, PartialEq)]
+    struct Config {
+        server: Server,
+        name: String,
+    }
+
+    let config = Config {
+        server: Server {
+            host: "localhost".to_string(),
+            port: 8080,
+        },
+        name: "app".to_string(),
+    };
+
+    let map: DynHashMap = DynHashMap::from_struct(&config).unwrap();
+    assert_eq!(map.get_path::<Server>("server"), Some(&config.server));
+    assert_eq!(map.get_path::<u16>("server.port"), Some(&8080));
+    assert_eq!(map.to_struct::<Config>(), Ok(config));
+
+    let config = map.to_struct::<Config>().unwrap();
+    let flattened: DynHashMap = DynHashMap::from_struct_flattened(&config).unwrap();
+    assert_eq!(
+        flattened.get(&"server.host".to_string()),
+        Some(&FacetValue::new("localhost".to_string()))
+    );
+    assert_eq!(
+        flattened.get(&"name".to_string()),
+        Some(&FacetValue::new("app".to_string()))
+    );
+    assert_eq!(flattened.get(&"server".to_string()), None);
+}
+
+#[test]
+fn test_env_and_args_ingestion() {
+    use facet::Facet;
+
+    #[derive(Facet, Debug, PartialEq)]
+    struct Settings {
+        port: u16,
+        verbose: bool,
+        name: String,
+    }
+
+    let vars = [
+        ("APP_PORT", "8080"),
+        ("APP_NAME", "demo"),
+        ("HOME", "/root"),
+    ]
+    .map(|(name, value)| (name.to_string(), value.to_string()));
+    let mut raw = FacetHashMap::<String, String>::from_vars("APP_", vars);
+    assert_eq!(raw.get(&"port".to_string()), Some(&"8080".to_string()));
+    assert_eq!(raw.get(&"home".to_string()), None);
+
+    let args = FacetHashMap::<String, String>::from_args(
+        ["prog", "--verbose", "--port=9090"].map(String::from),
+    );
+    for (key, value) in args.iter() {
+        raw.insert(key.clone(), value.clone());
+    }
+
+    let parsed: DynHashMap = raw.parse_fields(Settings::SHAPE).unwrap();
+    assert_eq!(
+        parsed.to_struct::<Settings>(),
+        Ok(Settings {
+            port: 9090,
+            verbose: true,
+            name: "demo".to_string(),
+        })
+    );
+
+    raw.insert("port".to_string(), "eighty".to_string());
+    let Err(error) = raw.parse_fields::<hashbrown::DefaultHashBuilder>(Settings::SHAPE) else {
+        panic!("`eighty` parsed as a u16");
+    };
+    assert_eq!(error.key, "port");
+    assert_eq!(error.shape, u16::SHAPE);
+
+    let ports = FacetHashMap::<String, String>::from_args(["--a=1", "--b=2"].map(String::from));
+    let ports: FacetHashMap<String, u16> = ports.parse_values().unwrap();
+    assert_eq!(ports.get(&"b".to_string()), Some(&2));
+}
+
+#[test]
+fn test_dyn_hashmap_get_as() {
+    use facet::Facet;
+
+    let mut flags: DynHashMap = DynHashMap::default();
+    flags.insert("port".to_string(), FacetValue::new("8080".to_string()));
+    flags.insert("ratio".to_string(), FacetValue::new(3u64));
+    flags.insert("beta".to_string(), FacetValue::new(true));
+    flags.insert("name".to_string(), FacetValue::new("demo".to_string()));
+
+    assert_eq!(flags.get_as::<u16>("port"), Ok(8080));
+    assert_eq!(flags.get_as::<f64>("ratio"), Ok(3.0));
+    assert_eq!(flags.get_as::<String>("beta"), Ok("true".to_string()));
+    assert_eq!(flags.get_as::<String>("name"), Ok("demo".to_string()));
+    assert_eq!(flags.get_as::<u16>("missing"), Err(CoerceError::Missing));
+    let error: Box<dyn std::error::Error> = flags.get_as::<u16>("name").unwrap_err().into();
+    assert_eq!(error.to_string(), "can't convert String to u16");
+    assert_eq!(
+        flags.get_as::<u16>("name"),
+        Err(CoerceError::Incompatible {
+            from: String::SHAPE,
+            to: u16::SHAPE,
+        })
+    );
+
+    assert_eq!(flags.get_or("name", 80u16), 80);
+    assert!(flags.get_or("beta", false));
+    assert_eq!(flags.get_or("timeout", 30u32), 30);
+}
+
+#[test]
+fn test_dyn_hashmap_get_as_leaves_value_in_place() {
+    use std::rc::Rc;
+
+    let mut flags: DynHashMap = DynHashMap::default();
+    flags.insert("name".to_string(), FacetValue::new("demo".to_string()));
+    let shared = Rc::new(1u8);
+    flags.insert("shared".to_string(), FacetValue::new(shared.clone()));
+
+    for _ in 0..2 {
+        assert_eq!(
+            flags.get_as::<Option<String>>("name"),
+            Ok(Some("demo".to_string()))
+        );
+    }
+    let wrapped = flags.get_as::<Option<Rc<u8>>>("shared").unwrap();
+    assert_eq!(Rc::strong_count(&shared), 3);
+    drop(wrapped);
+    drop(flags);
+    assert_eq!(Rc::strong_count(&shared), 1);
+}
+
+#[test]
+fn test_replace_contents() {
+    let mut config = FacetHashMap::<String, u32>::default();
+    config.insert("workers".to_string(), 4);
+    config.insert("timeout".to_string(), 30);
+    config.insert("retries".to_string(), 3);
+
+    let mut reloaded = FacetHashMap::<String, u32>::default();
+    reloaded.insert("workers".to_string(), 8);
+    reloaded.insert("timeout".to_string(), 30);
+    reloaded.insert("backlog".to_string(), 128);
+
+    let changes = config.replace_contents(reloaded);
+    assert_eq!(
+        changes,
+        ChangeSet {
+            added: vec!["backlog".to_string()],
+            removed: vec!["retries".to_string()],
+            changed: vec!["workers".to_string()],
+        }
+    );
+    assert_eq!(config.get(&"workers".to_string()), Some(&8));
+    assert_eq!(config.get(&"retries".to_string()), None);
+
+    let mut same = FacetHashMap::<String, u32>::default();
+    for (key, value) in config.iter() {
+        same.insert(key.clone(), *value);
+    }
+    assert!(config.replace_contents(same).is_empty());
+}
+
+#[test]
+fn test_merge_from() {
+    let mut global = FacetHashMap::<String, Vec<u32>>::default();
+    global.insert("a".to_string(), vec![1]);
+    global.insert("b".to_string(), vec![2]);
+
+    let mut local = FacetHashMap::<String, Vec<u32>>::default();
+    local.insert("b".to_string(), vec![20]);
+    local.insert("c".to_string(), vec![30]);
+    global.merge_from(local);
+
+    assert_eq!(global.get(&"a".to_string()), Some(&vec![1]));
+    assert_eq!(global.get(&"b".to_string()), Some(&vec![20]));
+    assert_eq!(global.get(&"c".to_string()), Some(&vec![30]));
+
+    let mut arena_map =
+        FacetHashMap::<u8, String, hashbrown::DefaultHashBuilder, ArenaStorage>::default();
+    arena_map.insert(1, "one".to_string());
+    let mut other =
+        FacetHashMap::<u8, String, hashbrown::DefaultHashBuilder, ArenaStorage>::default();
+    other.insert(2, "two".to_string());
+    arena_map.merge_from(other);
+    assert_eq!(arena_map.get(&2), Some(&"two".to_string()));
+
+    let words = FacetHashMap::<String, usize>::build_per_thread(
+        [vec!["x", "y"], vec!["y", "z"]],
+        |chunk| {
+            let mut map = FacetHashMap::default();
+            for word in chunk {
+                map.insert(word.to_string(), word.len());
+            }
+            map
+        },
+    );
+    let mut keys: Vec<_> = words.iter().map(|(key, _)| key.clone()).collect();
+    keys.sort();
+    assert_eq!(keys, ["x", "y", "z"]);
+
+    #[derive(facet::Facet, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    struct Version(u32, u32);
+
+    let mut versions = FacetHashMap::<Version, &str>::default();
+    versions.insert(Version(1, 0), "old");
+    let mut newer = FacetHashMap::<Version, &str>::default();
+    newer.insert(Version(1, 0), "replaced");
+    newer.insert(Version(2, 0), "new");
+    versions.merge_from(newer);
+    versions.merge_from(FacetHashMap::default());
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions.get(&Version(1, 0)), Some(&"replaced"));
+
+    let versions = FacetHashMap::<Version, u32>::build_per_thread([1, 2], |major| {
+        let mut map = FacetHashMap::default();
+        map.insert(Version(major, 0), major);
+        map
+    });
+    assert_eq!(versions.get(&Version(2, 0)), Some(&2));
+}
+
+#[test]
+fn test_extend_with() {
+    let mut counts = FacetHashMap::<String, u32>::default();
+    let words = "the cat saw the other cat and the dog".split(' ');
+    counts.extend_with(words.map(|word| (word.to_string(), 1)), |count, new| {
+        *count += new
+    });
+
+    assert_eq!(counts.get(&"the".to_string()), Some(&3));
+    assert_eq!(counts.get(&"cat".to_string()), Some(&2));
+    assert_eq!(counts.get(&"dog".to_string()), Some(&1));
+
+    let mut rollups = FacetHashMap::<u8, Vec<String>>::default();
+    rollups.extend_with(
+        [(1, vec!["a".to_string()]), (1, vec!["b".to_string()])],
+        |values, new| values.extend(new),
+    );
+    assert_eq!(
+        rollups.get(&1),
+        Some(&vec!["a".to_string(), "b".to_string()])
+    );
+}
+
+#[test]
+fn test_merge_with_resolver() {
+    let mut left = FacetHashMap::<String, Vec<u32>>::default();
+    left.insert("shared".to_string(), vec![1, 2]);
+    left.insert("left".to_string(), vec![3]);
+
+    let mut right = FacetHashMap::<String, Vec<u32>>::default();
+    right.insert("shared".to_string(), vec![4]);
+    right.insert("right".to_string(), vec![5]);
+
+    let mut conflicts = Vec::new();
+    let merged = left.merge(right, |key, mut left, right| {
+        conflicts.push(key.clone());
+        left.extend(right);
+        left
+    });
+
+    assert_eq!(conflicts, ["shared"]);
+    assert_eq!(merged.get(&"shared".to_string()), Some(&vec![1, 2, 4]));
+    assert_eq!(merged.get(&"left".to_string()), Some(&vec![3]));
+    assert_eq!(merged.get(&"right".to_string()), Some(&vec![5]));
+
+    let mut left = FacetHashMap::<String, String>::default();
+    left.insert("shared".to_string(), "left".to_string());
+    let mut right = FacetHashMap::<String, String>::default();
+    right.insert("shared".to_string(), "right".to_string());
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        left.merge(right, |_, _, _| panic!("conflicting values"))
+    }));
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_dyn_hashmap_increment() {
+    use facet::Facet;
+
+    let mut metrics: DynHashMap = DynHashMap::default();
+    metrics.insert("bytes".to_string(), FacetValue::new(250u8));
+    metrics.insert("load".to_string(), FacetValue::new(0.5f64));
+    metrics.insert("host".to_string(), FacetValue::new("a".to_string()));
+
+    metrics.increment("requests", 1).unwrap();
+    metrics.increment("requests", 2).unwrap();
+    metrics.increment("load", 1).unwrap();
+    metrics.increment("bytes", 5).unwrap();
+
+    assert_eq!(metrics.get_path::<i64>("requests"), Some(&3));
+    assert_eq!(metrics.get_path::<f64>("load"), Some(&1.5));
+    assert_eq!(metrics.get_path::<u8>("bytes"), Some(&255));
+    assert_eq!(
+        metrics.increment("bytes", 1),
+        Err(IncrementError::Overflow { shape: u8::SHAPE })
+    );
+    assert_eq!(
+        metrics.increment("bytes", 1).unwrap_err().to_string(),
+        "the sum overflows u8"
+    );
+    assert_eq!(
+        metrics.increment("host", 1),
+        Err(IncrementError::NotNumeric {
+            shape: String::SHAPE
+        })
+    );
+    metrics.insert("sessions".to_string(), FacetValue::new(5u64));
+    metrics.increment("sessions", -1).unwrap();
+    assert_eq!(metrics.get_path::<u64>("sessions"), Some(&4));
+    assert_eq!(
+        metrics.increment("sessions", -5),
+        Err(IncrementError::Overflow { shape: u64::SHAPE })
+    );
+    assert_eq!(metrics.get_path::<u64>("sessions"), Some(&4));
+    metrics.increment("bytes", -255).unwrap();
+    assert_eq!(metrics.get_path::<u8>("bytes"), Some(&0));
+
+    metrics.insert("offset".to_string(), FacetValue::new(-100i8));
+    metrics.increment("offset", 200).unwrap();
+    assert_eq!(metrics.get_path::<i8>("offset"), Some(&100));
+
+    metrics.insert("total".to_string(), FacetValue::new(1u128));
+    metrics.increment("total", -1).unwrap();
+    assert_eq!(metrics.get_path::<u128>("total"), Some(&0));
+    assert_eq!(
+        metrics.increment("total", -1),
+        Err(IncrementError::Overflow { shape: u128::SHAPE })
+    );
+}
+
+#[test]
+fn test_to_vec() {
+    let mut map = FacetHashMap::<String, Vec<u8>>::default();
+    map.insert("b".to_string(), vec![2]);
+    map.insert("c".to_string(), vec![3]);
+    map.insert("a".to_string(), vec![1]);
+
+    let mut unsorted = map.to_vec();
+    unsorted.sort();
+    let sorted = map.to_sorted_vec();
+    assert_eq!(unsorted, sorted);
+    assert_eq!(
+        sorted,
+        [
+            ("a".to_string(), vec![1]),
+            ("b".to_string(), vec![2]),
+            ("c".to_string(), vec![3]),
+        ]
+    );
+    assert_eq!(map.get(&"a".to_string()), Some(&vec![1]));
+}
+
+#[test]
+fn test_sample_iter() {
+    let mut map = FacetHashMap::<u32, u32>::default();
+    for i in 0..100 {
+        map.insert(i, i * 2);
+    }
+
+    // xorshift, good enough for a test
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let rng = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    let sample: Vec<_> = map.sample_iter(rng, 10).collect();
+    assert_eq!(sample.len(), 10);
+    for (key, value) in &sample {
+        assert_eq!(**value, **key * 2);
+    }
+    let mut keys: Vec<_> = sample.iter().map(|(key, _)| **key).collect();
+    keys.sort();
+    keys.dedup();
+    assert_eq!(keys.len(), 10);
+
+    assert_eq!(map.sample_iter(|| 0, 1000).count(), 100);
+    assert_eq!(map.sample_iter(|| 0, usize::MAX).count(), 100);
+}
+
+#[test]
+fn test_compact() {
+    let mut map =
+        FacetHashMap::<u32, String, hashbrown::DefaultHashBuilder, ArenaStorage>::default();
+    for i in 0..1000 {
+        map.insert(i, i.to_string());
+    }
+    for i in 0..990 {
+        map.remove(&i);
+    }
+
+    map.compact();
+    assert_eq!(map.len(), 10);
+    for i in 990..1000 {
+        assert_eq!(map.get(&i), Some(&i.to_string()));
+    }
+
+    let mut pooled =
+        FacetHashMap::<String, Vec<u8>, hashbrown::DefaultHashBuilder, PooledStorage>::default();
+    pooled.insert("a".to_string(), vec![1]);
+    pooled.insert("b".to_string(), vec![2]);
+    pooled.remove(&"a".to_string());
+    pooled.compact();
+    assert_eq!(pooled.get(&"b".to_string()), Some(&vec![2]));
+
+    #[derive(facet::Facet, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    struct Version(u32, u32);
+
+    let mut versions = FacetHashMap::<Version, String>::default();
+    versions.insert(Version(1, 0), "one".to_string());
+    versions.compact();
+    assert_eq!(versions.get(&Version(1, 0)), Some(&"one".to_string()));
+}
+
+#[cfg(feature = "leak-check")]
+#[test]
+fn test_leak_check() {
+    type Map =
+        FacetHashMap<'static, String, Vec<u8>, hashbrown::DefaultHashBuilder, LeakCheckStorage>;
+
+    let mut map = Map::default();
+    let counter = map.storage().counter();
+    map.insert("a".to_string(), vec![1]);
+    map.insert("b".to_string(), vec![2]);
+    map.insert("a".to_string(), vec![3]);
+    assert_eq!(counter.live_allocations(), 4);
+
+    map.remove(&"b".to_string());
+    map.extend_with([("a".to_string(), vec![4])], |old, new| old.extend(new));
+    assert_eq!(counter.live_allocations(), 2);
+
+    // the fresh backend compact moves everything into shares the counter
+    map.compact();
+    assert_eq!(counter.live_allocations(), 2);
+
+    let mut other = Map::default();
+    other.insert("a".to_string(), vec![5]);
+    other.insert("c".to_string(), vec![6]);
+    let other_counter = other.storage().counter();
+    let map = map.merge(other, |_, left, _| left);
+    other_counter.assert_no_leaks();
+    assert_eq!(counter.live_allocations(), 4);
+
+    drop(map);
+    counter.assert_no_leaks();
+
+    let mut map = Map::default();
+    let counter = map.storage().counter();
+    for key in ["a", "b", "c"] {
+        map.insert(key.to_string(), vec![1]);
+    }
+    let mut iter = map.into_iter();
+    let (_, value) = iter.next().unwrap();
+    assert_eq!(value, vec![1]);
+    assert_eq!(counter.live_allocations(), 4);
+    drop(iter);
+    counter.assert_no_leaks();
+
+    let mut map = Map::default();
+    let counter = map.storage().counter();
+    map.insert("a".to_string(), vec![1]);
+    map.insert("b".to_string(), vec![2]);
+    let mut keys = map.into_keys();
+    keys.next().unwrap();
+    assert_eq!(counter.live_allocations(), 2);
+    drop(keys);
+    counter.assert_no_leaks();
+
+    let mut dyn_map = FacetHashMap::<
+        String,
+        FacetValue,
+        hashbrown::DefaultHashBuilder,
+        LeakCheckStorage,
+    >::default();
+    let counter = dyn_map.storage().counter();
+    dyn_map.insert("x".to_string(), FacetValue::new(1u8));
+    drop(dyn_map);
+    counter.assert_no_leaks();
+    let mut inline_map = FacetHashMap::<
+        u32,
+        std::rc::Rc<u8>,
+        hashbrown::DefaultHashBuilder,
+        LeakCheckStorage,
+    >::default();
+    let counter = inline_map.storage().counter();
+    inline_map.insert(1, std::rc::Rc::new(1));
+    inline_map.insert(2, std::rc::Rc::new(2));
+    inline_map.insert(2, std::rc::Rc::new(3));
+    assert_eq!(counter.live_inline(), 2);
+    assert_eq!(counter.live_allocations(), 0);
+    inline_map.remove(&1);
+    assert_eq!(counter.live_inline(), 1);
+    std::mem::forget(inline_map);
+    assert_eq!(counter.live_inline(), 1);
+
+    let mut inline_map = FacetHashMap::<
+        u32,
+        std::rc::Rc<u8>,
+        hashbrown::DefaultHashBuilder,
+        LeakCheckStorage,
+    >::default();
+    let counter = inline_map.storage().counter();
+    inline_map.insert(1, std::rc::Rc::new(1));
+    inline_map.insert(2, std::rc::Rc::new(2));
+    inline_map.compact();
+    let mut values = inline_map.into_values();
+    values.next();
+    drop(values);
+    counter.assert_no_leaks();
+}
+
+#[test]
+fn test_alloc_hooks() {
+    use std::alloc::Layout;
+    use std::cell::Cell;
+
+    #[derive(Default)]
+    struct Bytes(Cell<usize>);
+
+    impl AllocHooks for Bytes {
+        fn on_allocate(&self, layout: Layout) {
+            self.0.set(self.0.get() + layout.size());
+        }
+
+        fn on_deallocate(&self, layout: Layout) {
+            self.0.set(self.0.get() - layout.size());
+        }
+    }
+
+    let mut map =
+        FacetHashMap::<u32, [u64; 4], hashbrown::DefaultHashBuilder, HookedStorage<Bytes>>::default(
+        );
+    map.insert(1, [0; 4]);
+    map.insert(2, [0; 4]);
+    assert_eq!(map.storage().hooks().0.get(), 64);
+
+    map.remove(&1);
+    assert_eq!(map.storage().hooks().0.get(), 32);
+
+    map.compact();
+    assert_eq!(map.storage().hooks().0.get(), 32);
+    map.remove(&2);
+    assert_eq!(map.storage().hooks().0.get(), 0);
+}
+
+#[test]
+fn test_smart_pointer_keys_and_values() {
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    let key = Arc::new("shared".to_string());
+    let value = Arc::new(vec![1u8, 2, 3]);
+
+    let mut map = FacetHashMap::<Arc<String>, Arc<Vec<u8>>>::default();
+    map.insert(key.clone(), value.clone());
+    assert_eq!(Arc::strong_count(&key), 2);
+    assert_eq!(Arc::strong_count(&value), 2);
+
+    // lookups go through the pointee, not the pointer
+    let equal_key = Arc::new("shared".to_string());
+    assert_eq!(map.get(&equal_key), Some(&value));
+
+    let snapshot = map.to_sorted_vec();
+    assert_eq!(Arc::strong_count(&value), 3);
+    drop(snapshot);
+
+    map.insert(equal_key, Arc::new(vec![4]));
+    assert_eq!(Arc::strong_count(&key), 2);
+    assert_eq!(Arc::strong_count(&value), 1);
+
+    drop(map);
+    assert_eq!(Arc::strong_count(&key), 1);
+
+    let rc_key = Rc::new(7u64);
+    let mut rc_map = FacetHashMap::<Rc<u64>, Rc<String>>::default();
+    rc_map.insert(rc_key.clone(), Rc::new("seven".to_string()));
+    assert_eq!(
+        rc_map.remove(&Rc::new(7)).as_deref(),
+        Some(&"seven".to_string())
+    );
+    assert_eq!(Rc::strong_count(&rc_key), 1);
+}
+
+#[test]
+fn test_lww_facet_map() {
+    let tag = |timestamp, actor| LwwTag { timestamp, actor };
+
+    let mut a = LwwFacetMap::<String, String>::default();
+    let mut b = LwwFacetMap::<String, String>::default();
+
+    a.insert("color".to_string(), "red".to_string(), tag(1, 1));
+    b.insert("color".to_string(), "blue".to_string(), tag(2, 2));
+    a.insert("size".to_string(), "small".to_string(), tag(3, 1));
+    b.insert("size".to_string(), "large".to_string(), tag(3, 2));
+    a.insert("shape".to_string(), "round".to_string(), tag(1, 1));
+    b.remove("shape".to_string(), tag(2, 2));
+
+    // an older local write doesn't override a newer one
+    a.insert("size".to_string(), "medium".to_string(), tag(2, 1));
+    assert_eq!(a.get(&"size".to_string()), Some(&"small".to_string()));
+
+    a.merge(b);
+    assert_eq!(a.get(&"color".to_string()), Some(&"blue".to_string()));
+    assert_eq!(a.get(&"size".to_string()), Some(&"large".to_string()));
+    assert_eq!(a.get(&"shape".to_string()), None);
+    assert_eq!(a.tag(&"shape".to_string()), Some(tag(2, 2)));
+}
+
+#[test]
+fn test_iter_partition() {
+    let mut map = FacetHashMap::<u32, u32>::default();
+    for i in 0..200 {
+        map.insert(i, i);
+    }
+
+    let mut seen = Vec::new();
+    for partition in 0..8 {
+        seen.extend(map.iter_partition(3, partition).map(|(key, _)| *key));
+    }
+    seen.sort();
+    assert_eq!(seen, (0..200).collect::<Vec<_>>());
+
+    assert_eq!(map.iter_partition(0, 0).count(), 200);
+}
+
+#[test]
+fn test_facet_dyn_value_map() {
+    use facet::{Facet, PtrMut};
+
+    let mut registry = FacetDynValueMap::<String>::new(Vec::<String>::SHAPE);
+    assert_eq!(
+        registry.insert("a".to_string(), vec!["x".to_string()]),
+        Ok(None)
+    );
+    assert_eq!(registry.insert("b".to_string(), 7u32), Err(7));
+
+    let mut plugin_value = std::mem::ManuallyDrop::new(vec!["y".to_string()]);
+    assert!(unsafe { registry.insert_erased("a".to_string(), PtrMut::new(&mut *plugin_value)) });
+
+    assert_eq!(
+        registry.get::<Vec<String>, _>(&"a".to_string()),
+        Some(&vec!["y".to_string()])
+    );
+    assert_eq!(registry.get::<u32, _>(&"a".to_string()), None);
+    assert!(registry.get_ptr(&"a".to_string()).is_some());
+
+    assert!(registry.remove(&"a".to_string()));
+    assert!(registry.get_ptr(&"a".to_string()).is_none());
+}
+
+#[test]
+fn test_facet_dyn_key_map() {
+    use facet::{Facet, PtrConst, PtrMut};
+
+    let mut by_id = FacetDynKeyMap::<String>::new(u64::SHAPE);
+    assert_eq!(by_id.insert(1u64, "one".to_string()), Ok(None));
+    assert_eq!(
+        by_id.insert("two", "two".to_string()),
+        Err(("two", "two".to_string()))
+    );
+
+    let mut key = 2u64;
+    assert_eq!(
+        unsafe { by_id.insert_erased(PtrMut::new(&mut key), "two".to_string()) },
+        None
+    );
+
+    assert_eq!(by_id.get(&2u64), Some(&"two".to_string()));
+    assert_eq!(by_id.get(&2u32), None);
+    assert_eq!(
+        unsafe { by_id.get_erased(PtrConst::new(&1u64)) },
+        Some(&"one".to_string())
+    );
+    assert_eq!(by_id.remove(&1u64), Some("one".to_string()));
+    assert_eq!(by_id.get(&1u64), None);
+}
+
+#[test]
+fn test_opaque_values() {
+    use std::rc::Rc;
+
+    use facet::Facet;
+
+    #[derive(Facet)]
+    struct Plugin {
+        name: String,
+        #[facet(opaque)]
+        run: Box<dyn Fn(u32) -> u32>,
+    }
+
+    let dropped = Rc::new(());
+    let guard = dropped.clone();
+    let mut plugins = FacetHashMap::<String, Plugin>::default();
+    plugins.insert(
+        "double".to_string(),
+        Plugin {
+            name: "double".to_string(),
+            run: Box::new(move |x| {
+                let _ = &guard;
+                x * 2
+            }),
+        },
+    );
+
+    let plugin = plugins.get(&"double".to_string()).unwrap();
+    assert_eq!(plugin.name, "double");
+    assert_eq!((plugin.run)(21), 42);
+
+    let value = FacetValue::new(plugins.remove(&"double".to_string()).unwrap());
+    assert_eq!(format!("{value:?}"), "<Plugin>");
+    assert!(value.try_clone().is_none());
+    drop(value);
+    assert_eq!(Rc::strong_count(&dropped), 1);
+}
+
+#[test]
+fn test_dyn_hashmap_option_values() {
+    let mut settings: DynHashMap = DynHashMap::default();
+    settings.insert_some("timeout", 30u32);
+    settings.insert("retries".to_string(), FacetValue::new(None::<u32>));
+    settings.insert("workers".to_string(), FacetValue::new(4u32));
+
+    assert_eq!(settings.get_flat::<u32>("timeout"), Some(&30));
+    assert_eq!(settings.get_flat::<u32>("retries"), None);
+    assert_eq!(settings.get_flat::<u32>("workers"), Some(&4));
+    assert_eq!(settings.get_flat::<String>("timeout"), None);
+
+    assert_eq!(settings.take_inner::<String>("timeout"), None);
+    assert_eq!(settings.take_inner::<u32>("timeout"), Some(30));
+    assert_eq!(settings.take_inner::<u32>("timeout"), None);
+    assert_eq!(settings.take_inner::<u32>("retries"), None);
+    assert!(settings.get(&"retries".to_string()).is_some());
+    assert_eq!(settings.take_inner::<u32>("workers"), Some(4));
+}
+
+#[test]
+fn test_nested_maps() {
+    use facet::Facet;
+
+    let mut settings = FacetHashMap::<String, FacetHashMap<String, bool>>::default();
+    assert_eq!(
+        settings.insert2("acme".to_string(), "beta".to_string(), true),
+        None
+    );
+    assert_eq!(
+        settings.insert2("acme".to_string(), "beta".to_string(), false),
+        Some(true)
+    );
+    settings.insert2("acme".to_string(), "dark_mode".to_string(), true);
+    settings.insert2("globex".to_string(), "beta".to_string(), true);
+
+    assert_eq!(
+        settings.get2(&"acme".to_string(), &"beta".to_string()),
+        Some(&false)
+    );
+    assert_eq!(
+        settings.get2(&"acme".to_string(), &"dark_mode".to_string()),
+        Some(&true)
+    );
+    assert_eq!(
+        settings.get2(&"globex".to_string(), &"dark_mode".to_string()),
+        None
+    );
+    assert_eq!(
+        settings.get2(&"initech".to_string(), &"beta".to_string()),
+        None
+    );
+
+    assert_eq!(
+        FacetHashMap::<String, u32>::SHAPE.to_string(),
+        "FacetHashMap<String, u32>"
+    );
+}
+
+#[test]
+fn test_dyn_hashmap_deep_merge() {
+    let layer = |port: u16, plugins: &[&str], debug: Option<bool>| {
+        let mut map: DynHashMap = DynHashMap::default();
+        map.set_path("server.port", port).unwrap();
+        let plugins: Vec<String> = plugins.iter().map(|plugin| plugin.to_string()).collect();
+        map.set_path("plugins", plugins).unwrap();
+        if let Some(debug) = debug {
+            map.set_path("server.debug", debug).unwrap();
+        }
+        map
+    };
+
+    let mut config = layer(80, &["auth"], Some(false));
+    config
+        .set_path("server.host", "localhost".to_string())
+        .unwrap();
+    config.deep_merge(layer(8080, &["metrics"], None), MergeStrategy::ConcatLists);
+
+    assert_eq!(config.get_path::<u16>("server.port"), Some(&8080));
+    assert_eq!(config.get_path::<bool>("server.debug"), Some(&false));
+    assert_eq!(
+        config.get_path::<String>("server.host"),
+        Some(&"localhost".to_string())
+    );
+    assert_eq!(
+        config.get_path::<Vec<String>>("plugins"),
+        Some(&vec!["auth".to_string(), "metrics".to_string()])
+    );
+
+    config.deep_merge(layer(9090, &["tracing"], None), MergeStrategy::ReplaceLists);
+    assert_eq!(
+        config.get_path::<Vec<String>>("plugins"),
+        Some(&vec!["tracing".to_string()])
+    );
+    assert_eq!(
+        config.get_path::<String>("server.host"),
+        Some(&"localhost".to_string())
+    );
+}
+
+#[test]
+fn test_nested_deep_merge() {
+    type Settings = FacetHashMap<'static, String, FacetHashMap<'static, String, Vec<u32>>>;
+
+    let mut base = Settings::default();
+    base.insert2("acme".to_string(), "ports".to_string(), vec![80]);
+    base.insert2("acme".to_string(), "admins".to_string(), vec![1]);
+    let mut layer = Settings::default();
+    layer.insert2("acme".to_string(), "ports".to_string(), vec![443]);
+    layer.insert2("globex".to_string(), "ports".to_string(), vec![8080]);
+
+    base.deep_merge(layer, MergeStrategy::ConcatLists);
+    assert_eq!(
+        base.get2(&"acme".to_string(), &"ports".to_string()),
+        Some(&vec![80, 443])
+    );
+    assert_eq!(
+        base.get2(&"acme".to_string(), &"admins".to_string()),
+        Some(&vec![1])
+    );
+    assert_eq!(
+        base.get2(&"globex".to_string(), &"ports".to_string()),
+        Some(&vec![8080])
+    );
+
+    let mut layer = Settings::default();
+    layer.insert2("acme".to_string(), "ports".to_string(), vec![8443]);
+    base.deep_merge(layer, MergeStrategy::ReplaceLists);
+    assert_eq!(
+        base.get2(&"acme".to_string(), &"ports".to_string()),
+        Some(&vec![8443])
+    );
+}
+
+#[test]
+fn test_borrowed_keys() {
+    use facet::Facet;
+
+    #[derive(Facet, PartialEq, Eq, Hash, Debug, Clone)]
+    struct Name<'a> {
+        first: &'a str,
+        last: &'a str,
+    }
+
+    // maps are covariant in their borrowed keys, like std's HashMap
+    fn _shorten<'short>(
+        map: FacetHashMap<'static, &'static str, u32>,
+    ) -> FacetHashMap<'short, &'short str, u32> {
+        map
+    }
+
+    let source = String::from("ada lovelace grace hopper");
+    let words: Vec<&str> = source.split(' ').collect();
+
+    let mut by_word = FacetHashMap::<&str, usize>::default();
+    for (i, word) in words.iter().enumerate() {
+        by_word.insert(word, i);
+    }
+    let lookup = String::from("grace");
+    assert_eq!(by_word.get(&lookup.as_str()), Some(&2));
+    assert_eq!(by_word.remove(&"ada"), Some(0));
+    assert_eq!(by_word.insert("hopper", 30), Some(3));
+    let mut keys: Vec<&str> = by_word.iter().map(|(key, _)| *key).collect();
+    keys.sort();
+    assert_eq!(keys, ["grace", "hopper", "lovelace"]);
+
+    let mut people = FacetHashMap::<Name, u32>::default();
+    people.insert(
+        Name {
+            first: words[0],
+            last: words[1],
+        },
+        1815,
+    );
+    people.insert(
+        Name {
+            first: words[2],
+            last: words[3],
+        },
+        1906,
+    );
+    let grace = Name {
+        first: &lookup,
+        last: "hopper",
+    };
+    assert_eq!(people.get(&grace), Some(&1906));
+    assert_eq!(people.to_vec().len(), 2);
+    assert_eq!(people.remove(&grace), Some(1906));
+    assert_eq!(people.get(&grace), None);
+}
+
+#[test]
+fn test_get_borrowed() {
+    use std::path::{Path, PathBuf};
+
+    let mut sizes = FacetHashMap::<PathBuf, u64>::default();
+    sizes.insert(PathBuf::from("/etc/hosts"), 220);
+    sizes.insert(PathBuf::from("/etc/passwd"), 1800);
+
+    assert_eq!(sizes.get_borrowed(Path::new("/etc/hosts")), Some(&220));
+    assert_eq!(sizes.get_borrowed(Path::new("/etc/shadow")), None);
+
+    let mut names = FacetHashMap::<String, u32>::default();
+    names.insert("alice".to_string(), 1);
+    assert_eq!(names.get_borrowed("alice"), Some(&1));
+    assert_eq!(names.get_borrowed("bob"), None);
+}
+
+#[test]
+fn test_any_shape_map() {
+    use facet::Facet;
+
+    #[derive(Facet, Debug, PartialEq)]
+    struct RequestId(u64);
+
+    #[derive(Facet, Debug, PartialEq)]
+    struct User {
+        name: String,
+    }
+
+    let mut extensions = AnyShapeMap::new();
+    assert_eq!(extensions.insert(RequestId(1)), None);
+    assert_eq!(extensions.insert(RequestId(2)), Some(RequestId(1)));
+    extensions.insert_value(FacetValue::new(User {
+        name: "ada".to_string(),
+    }));
+    extensions.insert("plain string".to_string());
+
+    assert_eq!(extensions.len(), 3);
+    assert_eq!(extensions.get::<RequestId>(), Some(&RequestId(2)));
+    extensions.get_mut::<User>().unwrap().name.push('!');
+    assert_eq!(extensions.get::<User>().unwrap().name, "ada!");
+    assert!(extensions.get_value(String::SHAPE).is_some());
+    assert!(!extensions.contains::<u32>());
+
+    assert_eq!(extensions.remove::<RequestId>(), Some(RequestId(2)));
+    assert_eq!(extensions.get::<RequestId>(), None);
+    extensions.insert(1u32);
+    let old = extensions.insert(FacetValue::new(2u32)).unwrap();
+    assert_eq!(old.downcast_ref::<u32>(), Some(&1));
+    assert_eq!(extensions.get::<u32>(), Some(&2));
+    assert!(extensions.get::<FacetValue>().is_none());
+    assert!(extensions.remove::<FacetValue>().is_none());
+}
+
+#[test]
+fn test_by_address_keys() {
+    use std::sync::Arc;
+
+    let a = Arc::new("node".to_string());
+    let b = Arc::new("node".to_string());
+
+    let mut seen = FacetHashMap::<ByAddress<Arc<String>>, u32>::default();
+    seen.insert(ByAddress(a.clone()), 1);
+    seen.insert(ByAddress(b.clone()), 2);
+    assert_eq!(seen.insert(ByAddress(a.clone()), 3), Some(1));
+
+    assert_eq!(seen.get(&ByAddress(a.clone())), Some(&3));
+    assert_eq!(seen.get(&ByAddress(b)), Some(&2));
+    assert_eq!(seen.get(&ByAddress(Arc::new("node".to_string()))), None);
+
+    drop(seen);
+    assert_eq!(Arc::strong_count(&a), 1);
+}
+
+#[test]
+fn test_get_tuple() {
+    let mut quotas = FacetHashMap::<(String, String), u32>::default();
+    quotas.insert(("acme".to_string(), "storage".to_string()), 100);
+    quotas.insert(("acme".to_string(), "seats".to_string()), 5);
+
+    let tenant = "acme".to_string();
+    let resource = "seats".to_string();
+    assert_eq!(quotas.get_tuple((&tenant, &resource)), Some(&5));
+    assert_eq!(quotas.get_tuple((&tenant, &"cpu".to_string())), None);
+
+    quotas.insert(("globex".to_string(), "seats".to_string()), 50);
+    let mut acme: Vec<_> = quotas
+        .iter_prefix(&tenant)
+        .map(|((_, resource), quota)| (resource.as_str(), *quota))
+        .collect();
+    acme.sort();
+    assert_eq!(acme, [("seats", 5), ("storage", 100)]);
+    assert_eq!(quotas.iter_prefix(&"initech".to_string()).count(), 0);
+}
+
+#[test]
+fn test_storage_report() {
+    let mut map = FacetHashMap::<u32, String>::default();
+    map.insert(1, "one".to_string());
+    map.insert(2, "two".to_string());
+
+    let string_size = std::mem::size_of::<String>();
+    let report = map.storage_report();
+    assert_eq!(report.inline, 2);
+    assert_eq!(report.boxed, 2);
+    assert_eq!(report.boxed_sizes.get(&string_size), Some(&2));
+
+    let mut settings: DynHashMap = DynHashMap::default();
+    settings.insert("port".to_string(), FacetValue::new(8080u16));
+    settings.insert("name".to_string(), FacetValue::new("demo".to_string()));
+    let report = settings.storage_report();
+    // keys and FacetValues are boxed, and so is the String inside one of them
+    assert_eq!(report.inline, 1);
+    assert_eq!(report.boxed, 5);
+}
+
+#[test]
+fn test_boxed_storage() {
+    let mut map = FacetHashMap::<u32, u32, hashbrown::DefaultHashBuilder, BoxedStorage>::default();
+    map.insert(0, 0);
+    let address = map.get(&0).unwrap() as *const u32;
+    for i in 1..100 {
+        map.insert(i, i);
+    }
+    assert_eq!(map.get(&0).unwrap() as *const u32, address);
+    for i in 1..100 {
+        map.remove(&i);
+    }
+    map.compact();
+    assert_eq!(map.get(&0).unwrap() as *const u32, address);
+    for i in 1..100 {
+        map.insert(i, i);
+    }
+
+    let report = map.storage_report();
+    assert_eq!(report.inline, 0);
+    assert_eq!(report.boxed, 200);
+}
+
+#[test]
+fn test_niche_option_values_are_inline() {
+    let mut map = FacetHashMap::<u32, Option<Box<u64>>>::default();
+    map.insert(1, Some(Box::new(1)));
+    map.insert(2, None);
+    assert_eq!(map.storage_report().boxed, 0);
+
+    let mut map = FacetHashMap::<u32, Option<std::num::NonZeroUsize>>::default();
+    map.insert(1, std::num::NonZeroUsize::new(1));
+    assert_eq!(map.storage_report().boxed, 0);
+    assert_eq!(map.get(&1), Some(&std::num::NonZeroUsize::new(1)));
+}
+
+#[test]
+fn test_total_float_keys() {
+    use facet::Facet;
+
+    let mut map = FacetHashMap::<TotalFloat<f64>, &str>::default();
+    map.insert(TotalFloat::new(0.0), "zero");
+    assert_eq!(
+        map.insert(TotalFloat::new(-0.0), "negative zero"),
+        Some("zero")
+    );
+    map.insert(TotalFloat::new(f64::NAN), "nan");
+    assert_eq!(map.get(&TotalFloat::new(-f64::NAN)), Some(&"nan"));
+    map.insert(TotalFloat::new(1.5), "one and a half");
+
+    let sorted: Vec<_> = map
+        .to_sorted_vec()
+        .into_iter()
+        .map(|(key, _)| key.get())
+        .collect();
+    assert_eq!(sorted[..2], [0.0, 1.5]);
+    assert!(sorted[2].is_nan());
+
+    assert_eq!(format!("{}", TotalFloat::<f32>::SHAPE), "TotalFloat<f32>");
+}
+
+#[test]
+fn test_enum_keys() {
+    #[derive(facet::Facet, Debug, Clone, PartialEq, Eq, Hash)]
+    #[repr(u8)]
+    enum Event {
+        Click { x: u32, y: u32 },
+        Key(String),
+        Quit,
+    }
+
+    let mut map = FacetHashMap::<Event, u32>::default();
+    map.insert(Event::Click { x: 1, y: 2 }, 1);
+    map.insert(Event::Key("a".to_string()), 2);
+    map.insert(Event::Quit, 3);
+    assert_eq!(map.insert(Event::Key("a".to_string()), 4), Some(2));
+
+    assert_eq!(map.get(&Event::Click { x: 1, y: 2 }), Some(&1));
+    assert_eq!(map.get(&Event::Click { x: 2, y: 1 }), None);
+    assert_eq!(map.get(&Event::Key("a".to_string())), Some(&4));
+    assert_eq!(map.get(&Event::Quit), Some(&3));
+
+    // the tags only differ in their high byte
+    #[derive(facet::Facet, Debug, PartialEq, Eq, Hash)]
+    #[repr(u16)]
+    enum Opcode {
+        Nop = 0x01,
+        Load(u16) = 0x101,
+        Store(u16) = 0x201,
+    }
+
+    let mut map = FacetHashMap::<Opcode, &str>::default();
+    map.insert(Opcode::Nop, "nop");
+    map.insert(Opcode::Load(7), "load");
+    map.insert(Opcode::Store(7), "store");
+    assert_eq!(map.len(), 3);
+    assert_eq!(map.get(&Opcode::Load(7)), Some(&"load"));
+    assert_eq!(map.get(&Opcode::Store(7)), Some(&"store"));
+    assert_eq!(map.get(&Opcode::Store(8)), None);
+    assert_eq!(map.remove(&Opcode::Nop), Some("nop"));
+    assert_eq!(map.get(&Opcode::Load(7)), Some(&"load"));
+}
+
+#[test]
+fn test_strict_construction() {
+    let map = FacetHashMap::<String, u32>::from_iter_strict([
+        ("port".to_string(), 8080),
+        ("workers".to_string(), 4),
+    ])
+    .unwrap();
+    assert_eq!(map.get(&"port".to_string()), Some(&8080));
+
+    let Err(error) = FacetHashMap::<String, u32>::from_iter_strict([
+        ("port".to_string(), 8080),
+        ("port".to_string(), 9090),
+        ("workers".to_string(), 4),
+        ("port".to_string(), 7070),
+    ]) else {
+        panic!("duplicate keys should be rejected");
+    };
+    assert_eq!(error.keys, ["\"port\""]);
+    assert_eq!(error.to_string(), "duplicate keys: \"port\"");
+
+    let mut map = map;
+    let error = map
+        .extend_strict([("workers".to_string(), 8), ("timeout".to_string(), 30)])
+        .unwrap_err();
+    assert_eq!(error.keys, ["\"workers\""]);
+    assert_eq!(map.get(&"workers".to_string()), Some(&4));
+    assert_eq!(map.get(&"timeout".to_string()), Some(&30));
+}
+
+#[test]
+fn test_try_extend() {
+    let raw = [("a", "1"), ("b", "two"), ("c", "3"), ("d", "-")];
+    let parsed = || raw.map(|(key, value)| (key, value.parse::<u32>()));
+
+    let mut map = FacetHashMap::<&str, u32>::default();
+    let (key, _) = map.try_extend(parsed()).unwrap_err();
+    assert_eq!(key, "b");
+    assert_eq!(map.get(&"a"), Some(&1));
+    assert_eq!(map.get(&"c"), None);
+
+    let mut map = FacetHashMap::<&str, u32>::default();
+    let errors = map.try_extend_all(parsed()).unwrap_err();
+    let failed: Vec<_> = errors.iter().map(|(key, _)| *key).collect();
+    assert_eq!(failed, ["b", "d"]);
+    assert_eq!(map.get(&"c"), Some(&3));
+
+    assert!(map.try_extend([("e", Ok::<_, ()>(5))]).is_ok());
+}
+
+#[test]
+fn test_parse_keys() {
+    #[derive(facet::Facet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[repr(u16)]
+    enum Level {
+        Debug,
+        Info = 300,
+        Custom(u8),
+    }
+
+    let mut ports = FacetHashMap::<String, &str>::default();
+    ports.insert("8080".to_string(), "http");
+    ports.insert("8443".to_string(), "https");
+    let parsed = ports
+        .parse_keys::<u16, hashbrown::DefaultHashBuilder>()
+        .unwrap();
+    assert_eq!(parsed.get(&8443), Some(&"https"));
+
+    ports.insert("ftp".to_string(), "ftp");
+    let Err(error) = ports.parse_keys::<u16, hashbrown::DefaultHashBuilder>() else {
+        panic!("`ftp` isn't a port number");
+    };
+    assert_eq!(error.key, "ftp");
+    assert!(
+        error
+            .to_string()
+            .starts_with("can't parse key \"ftp\" as u16: ")
+    );
+    assert!(std::error::Error::source(&error).is_some());
+
+    let mut levels = FacetHashMap::<String, u32>::default();
+    levels.insert("Info".to_string(), 1);
+    levels.insert("Debug".to_string(), 2);
+    let parsed = levels
+        .parse_keys::<Level, hashbrown::DefaultHashBuilder>()
+        .unwrap();
+    assert_eq!(parsed.get(&Level::Info), Some(&1));
+    assert_eq!(parsed.get(&Level::Debug), Some(&2));
+    assert_eq!(parsed.get(&Level::Custom(0)), None);
+
+    levels.insert("Custom".to_string(), 3);
+    let Err(error) = levels.parse_keys::<Level, hashbrown::DefaultHashBuilder>() else {
+        panic!("`Custom` isn't a unit variant");
+    };
+    assert_eq!(error.key, "Custom");
+}
+
+#[test]
+fn test_to_string_keyed() {
+    let mut map = FacetHashMap::<u16, &str>::default();
+    map.insert(8080, "http");
+    map.insert(8443, "https");
+
+    let string_keyed = map.to_string_keyed::<hashbrown::DefaultHashBuilder>();
+    assert_eq!(string_keyed.get(&"8443".to_string()), Some(&"https"));
+    assert_eq!(string_keyed.len(), 2);
+}
+
+#[test]
+fn test_render_table() {
+    #[derive(facet::Facet)]
+    struct Service {
+        port: u16,
+        protocol: String,
+        tags: Vec<&'static str>,
+    }
+
+    let mut services = FacetHashMap::<String, Service>::default();
+    services.insert(
+        "web".to_string(),
+        Service {
+            port: 443,
+            protocol: "https".to_string(),
+            tags: vec!["public"],
+        },
+    );
+    services.insert(
+        "database".to_string(),
+        Service {
+            port: 5432,
+            protocol: "tcp".to_string(),
+            tags: vec![],
+        },
+    );
+
+    assert_eq!(
+        services.render_table(),
+        "\
+key       port  protocol  tags
+--------  ----  --------  ----------
+database  5432  tcp       []
+web       443   https     [\"public\"]
+"
+    );
+
+    let mut counts = FacetHashMap::<&str, u32>::default();
+    counts.insert("a", 1);
+    assert_eq!(counts.render_table(), "key  value\n---  -----\na    1\n");
+
+    let mut ports = FacetHashMap::<u32, &str>::default();
+    ports.insert(10, "ten");
+    ports.insert(9, "nine");
+    assert_eq!(
+        ports.render_table(),
+        "key  value\n---  -----\n9    nine\n10   ten\n"
+    );
+}
+
+#[test]
+fn test_generation() {
+    let mut map = FacetHashMap::<String, u32>::default();
+    let initial = map.generation();
+
+    map.insert("a".to_string(), 1);
+    let after_insert = map.generation();
+    assert!(after_insert > initial);
+
+    map.get(&"a".to_string());
+    map.to_vec();
+    assert_eq!(map.generation(), after_insert);
+
+    map.remove(&"missing".to_string());
+    assert_eq!(map.generation(), after_insert);
+    map.remove(&"a".to_string());
+    let after_remove = map.generation();
+    assert!(after_remove > after_insert);
+
+    map.replace_contents(FacetHashMap::default());
+    assert!(map.generation() > after_remove);
+}
+
+#[test]
+fn test_watched_map() {
+    let mut config = WatchedFacetMap::<String, u32>::default();
+    let port_changes = config.subscribe_key("port".to_string());
+    let limit_changes = config.subscribe(|key| key.ends_with("_limit"));
+
+    config.insert("port".to_string(), 8080);
+    config.insert("port".to_string(), 9090);
+    config.insert("rate_limit".to_string(), 100);
+    config.insert("name".to_string(), 1);
+    config.remove(&"port".to_string());
+
+    let port_events: Vec<_> = port_changes.try_iter().collect();
+    assert_eq!(
+        port_events,
+        [
+            WatchEvent::Inserted {
+                key: "port".to_string(),
+                value: 8080
+            },
+            WatchEvent::Updated {
+                key: "port".to_string(),
+                old: 8080,
+                new: 9090
+            },
+            WatchEvent::Removed {
+                key: "port".to_string(),
+                value: 9090
+            },
+        ]
+    );
+    assert_eq!(limit_changes.try_iter().count(), 1);
+
+    drop(limit_changes);
+    config.insert("memory_limit".to_string(), 1);
+    assert_eq!(config.get(&"memory_limit".to_string()), Some(&1));
+}
+
+#[test]
+fn test_scoped_insert() {
+    let mut config = FacetHashMap::<&str, u32>::default();
+    config.insert("timeout", 30);
+
+    {
+        let mut overridden = config.scoped_insert("timeout", 1);
+        assert_eq!(overridden.get(&"timeout"), Some(&1));
+
+        let added = overridden.scoped_insert("retries", 5);
+        assert_eq!(added.get(&"retries"), Some(&5));
+        assert_eq!(added.get(&"timeout"), Some(&1));
+    }
+
+    assert_eq!(config.get(&"timeout"), Some(&30));
+    assert_eq!(config.get(&"retries"), None);
+}
+
+#[cfg(feature = "entry-timestamps")]
+#[test]
+fn test_entry_timestamps() {
+    let mut map = FacetHashMap::<&str, u32>::default();
+    map.insert("a", 1);
+    map.insert("b", 2);
+    let created = map.get_meta(&"a").unwrap();
+    assert_eq!(created.created_at, created.updated_at);
+
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    map.insert("a", 3);
+    let updated = map.get_meta(&"a").unwrap();
+    assert_eq!(updated.created_at, created.created_at);
+    assert!(updated.updated_at > created.updated_at);
+
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    *map.get_mut(&"b").unwrap() += 1;
+    let stale: Vec<_> = map
+        .iter_meta()
+        .filter(|(_, _, meta)| meta.updated_at <= updated.updated_at)
+        .map(|(key, _, _)| *key)
+        .collect();
+    assert_eq!(stale, ["a"]);
+    assert_eq!(map.get_meta(&"c"), None);
+}
+
+#[test]
+fn test_epoch_eviction() {
+    let mut routes = EpochFacetMap::<String, u16>::default();
+    routes.insert("web".to_string(), 80, 1);
+    routes.insert("api".to_string(), 8080, 1);
+    routes.insert("admin".to_string(), 9000, 1);
+
+    routes.insert("web".to_string(), 443, 2);
+    assert!(routes.refresh(&"api".to_string(), 2));
+    assert!(!routes.refresh(&"metrics".to_string(), 2));
+
+    assert_eq!(routes.remove_older_than(2), 1);
+    assert_eq!(routes.get(&"admin".to_string()), None);
+    assert_eq!(routes.get(&"web".to_string()), Some(&443));
+    assert_eq!(routes.get(&"api".to_string()), Some(&8080));
+    assert_eq!(routes.epoch(&"api".to_string()), Some(2));
+    assert_eq!(routes.remove_older_than(2), 0);
+}
+
+#[test]
+fn test_maintenance_tick() {
+    let mut map =
+        FacetHashMap::<u32, String, hashbrown::DefaultHashBuilder, PooledStorage>::default();
+    for i in 0..10 {
+        map.insert(i, i.to_string());
+    }
+    for i in 0..5 {
+        map.remove(&i);
+    }
+    map.tick();
+    assert_eq!(map.get(&7), Some(&"7".to_string()));
+    assert_eq!(map.storage_report().boxed, 5);
+    let mut map = FacetHashMap::<u32, String>::default();
+    map.insert(1, "one".to_string());
+    let address = map.get(&1).unwrap() as *const String;
+    map.tick();
+    assert_eq!(map.get(&1).unwrap() as *const String, address);
+}
+
+#[cfg(feature = "background-maintenance")]
+#[test]
+fn test_background_maintenance() {
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Ticks(usize);
+
+    impl Maintenance for Ticks {
+        fn tick(&mut self) {
+            self.0 += 1;
+        }
+    }
+
+    let ticks = Arc::new(Mutex::new(Ticks::default()));
+    let handle = spawn_maintenance(&ticks, std::time::Duration::from_millis(1));
+    while ticks.lock().unwrap().0 < 3 {
+        std::thread::yield_now();
+    }
+
+    drop(ticks);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_remove() {
+    let mut map = FacetHashMap::<String, Vec<u32>>::default();
+    map.insert("a".to_string(), vec![1, 2]);
+    map.insert("b".to_string(), vec![3]);
+
+    assert_eq!(map.remove(&"a".to_string()), Some(vec![1, 2]));
+    assert_eq!(map.remove(&"a".to_string()), None);
+    assert_eq!(map.get(&"a".to_string()), None);
+    assert_eq!(map.get(&"b".to_string()), Some(&vec![3]));
+
+    let mut inline = FacetHashMap::<u32, u32>::default();
+    inline.insert(1, 10);
+    assert_eq!(inline.remove(&1), Some(10));
+    assert_eq!(inline.remove(&1), None);
+}
+
+#[test]
+fn test_get_mut() {
+    let mut counters = FacetHashMap::<&str, u64>::default();
+    counters.insert("hits", 0);
+    for _ in 0..3 {
+        *counters.get_mut(&"hits").unwrap() += 1;
+    }
+    assert_eq!(counters.get(&"hits"), Some(&3));
+    assert!(counters.get_mut(&"misses").is_none());
+
+    let mut lists = FacetHashMap::<String, Vec<u32>>::default();
+    lists.insert("a".to_string(), vec![1]);
+    lists.get_mut(&"a".to_string()).unwrap().push(2);
+    assert_eq!(lists.get(&"a".to_string()), Some(&vec![1, 2]));
+}
+
+#[test]
+fn test_contains_key() {
+    let mut map = FacetHashMap::<String, u32>::default();
+    map.insert("a".to_string(), 1);
+    assert!(map.contains_key(&"a".to_string()));
+    assert!(!map.contains_key(&"b".to_string()));
+    assert!(map.contains_key_borrowed("a"));
+    assert!(!map.contains_key_borrowed("b"));
+}
+
+#[test]
+fn test_len() {
+    let mut map = FacetHashMap::<String, u32>::default();
+    assert!(map.is_empty());
+    map.insert("a".to_string(), 1);
+    map.insert("b".to_string(), 2);
+    map.insert("a".to_string(), 3);
+    assert_eq!(map.len(), 2);
+    map.remove(&"a".to_string());
+    assert_eq!(map.len(), 1);
+    assert!(!map.is_empty());
+}
+
+#[test]
+fn test_clear() {
+    let mut map =
+        FacetHashMap::<String, Vec<u32>, hashbrown::DefaultHashBuilder, PooledStorage>::default();
+    for round in 0..3 {
+        for i in 0..10 {
+            map.insert(i.to_string(), vec![round; i]);
+        }
+        assert_eq!(map.len(), 10);
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.get(&"1".to_string()), None);
+    }
+}
+
+#[test]
+fn test_reserve() {
+    #[derive(facet::Facet, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    struct Version(u32, u32);
+
+    let mut map = FacetHashMap::<u32, String>::with_capacity(100);
+    let capacity = map.capacity();
+    assert!(capacity >= 100);
+    for i in 0..100 {
+        map.insert(i, i.to_string());
+    }
+    assert_eq!(map.capacity(), capacity);
+
+    let mut versions = FacetHashMap::<Version, u32>::default();
+    versions.reserve(10);
+    assert!(versions.capacity() >= 10);
+    versions.insert(Version(1, 0), 1);
+    assert_eq!(versions.get(&Version(1, 0)), Some(&1));
+}
+
+#[test]
+fn test_entry() {
+    let mut counts = FacetHashMap::<String, u32>::default();
+    for word in "a b a c a b".split(' ') {
+        *counts.entry(word.to_string()).or_insert(0) += 1;
+    }
+    assert_eq!(counts.get(&"a".to_string()), Some(&3));
+    assert_eq!(counts.get(&"c".to_string()), Some(&1));
+
+    counts
+        .entry("c".to_string())
+        .and_modify(|count| *count *= 10)
+        .or_default();
+    counts
+        .entry("d".to_string())
+        .and_modify(|count| *count *= 10)
+        .or_default();
+    assert_eq!(counts.get(&"c".to_string()), Some(&10));
+    assert_eq!(counts.get(&"d".to_string()), Some(&0));
+
+    let len = counts
+        .entry("e".to_string())
+        .or_insert_with_key(|key| key.len() as u32);
+    assert_eq!(*len, 1);
+
+    match counts.entry("b".to_string()) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.key(), "b");
+            assert_eq!(entry.insert(20), 2);
+            assert_eq!(entry.remove_entry(), ("b".to_string(), 20));
+        }
+        Entry::Vacant(_) => panic!("`b` should be occupied"),
+    }
+    match counts.entry("b".to_string()) {
+        Entry::Occupied(_) => panic!("`b` was removed"),
+        Entry::Vacant(entry) => assert_eq!(entry.into_key(), "b"),
+    }
+    assert_eq!(counts.len(), 4);
+
+    // keys without a hash fn go through the sorted entries
+    #[derive(facet::Facet, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    struct Version(u32, u32);
+
+    let mut releases = FacetHashMap::<Version, Vec<&str>>::default();
+    releases.entry(Version(1, 0)).or_default().push("first");
+    releases.entry(Version(0, 9)).or_default().push("beta");
+    releases.entry(Version(1, 0)).or_default().push("again");
+    assert_eq!(releases.get(&Version(1, 0)), Some(&vec!["first", "again"]));
+    let Entry::Occupied(entry) = releases.entry(Version(0, 9)) else {
+        panic!("0.9 should be occupied");
+    };
+    assert_eq!(entry.remove(), ["beta"]);
+    assert_eq!(releases.len(), 1);
+}
+
+#[test]
+fn test_entry_ref() {
+    let mut counts = FacetHashMap::<String, u32>::default();
+    for word in "a b a c a".split(' ') {
+        *counts.entry_ref(word).or_insert(0) += 1;
+    }
+    assert_eq!(counts.get_borrowed("a"), Some(&3));
+    assert_eq!(counts.len(), 3);
+
+    counts.entry_ref("b").and_modify(|count| *count += 10);
+    assert_eq!(counts.get_borrowed("b"), Some(&11));
+    match counts.entry_ref("d") {
+        EntryRef::Occupied(_) => panic!("`d` isn't in the map"),
+        EntryRef::Vacant(entry) => assert_eq!(entry.key(), "d"),
+    }
+    assert!(!counts.contains_key_borrowed("d"));
+
+    #[derive(facet::Facet, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    struct Name(String);
+
+    impl std::borrow::Borrow<str> for Name {
+        fn borrow(&self) -> &str {
+            &self.0
+        }
+    }
+
+    impl From<&str> for Name {
+        fn from(name: &str) -> Self {
+            Name(name.to_string())
+        }
+    }
+
+    // Name has no hash fn, so its entries are kept sorted
+    let mut sorted = FacetHashMap::<Name, u32>::default();
+    for name in ["m", "z", "a", "m"] {
+        *sorted.entry_ref(name).or_default() += 1;
+    }
+    let keys: Vec<_> = sorted.iter().map(|(key, _)| key.0.as_str()).collect();
+    assert_eq!(keys, ["a", "m", "z"]);
+    assert_eq!(sorted.get(&Name("m".to_string())), Some(&2));
+}
+
+#[test]
+fn test_iter() {
+    #[derive(facet::Facet, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    struct Version(u32, u32);
+
+    let mut map = FacetHashMap::<String, u32>::default();
+    for (i, key) in ["a", "b", "c"].into_iter().enumerate() {
+        map.insert(key.to_string(), i as u32);
+    }
+
+    let mut iter = map.iter();
+    assert_eq!(iter.len(), 3);
+    iter.next();
+    assert_eq!(iter.len(), 2);
+    let mut entries: Vec<_> = (&map).into_iter().collect();
+    entries.sort();
+    assert_eq!(
+        entries,
+        [
+            (&"a".to_string(), &0),
+            (&"b".to_string(), &1),
+            (&"c".to_string(), &2)
+        ]
+    );
+
+    let mut sorted = FacetHashMap::<Version, &str>::default();
+    sorted.insert(Version(2, 0), "b");
+    sorted.insert(Version(1, 0), "a");
+    let values: Vec<_> = sorted.iter().map(|(_, value)| *value).collect();
+    assert_eq!(values, ["a", "b"]);
+    let mut iter = sorted.iter();
+    iter.by_ref().count();
+    assert_eq!(iter.next(), None);
+}
+
+#[test]
+fn test_iter_mut() {
+    let mut map = FacetHashMap::<String, Vec<u32>>::default();
+    map.insert("a".to_string(), vec![3, 1, 2]);
+    map.insert("b".to_string(), vec![2, 1]);
+
+    let mut iter = map.iter_mut();
+    assert_eq!(iter.len(), 2);
+    for (_, value) in iter.by_ref() {
+        value.sort();
+    }
+    assert_eq!(iter.next(), None);
+    for (key, value) in &mut map {
+        value.push(key.len() as u32);
+    }
+
+    assert_eq!(map.get(&"a".to_string()), Some(&vec![1, 2, 3, 1]));
+    assert_eq!(map.get(&"b".to_string()), Some(&vec![1, 2, 1]));
+}
+
+#[test]
+fn test_into_iter() {
+    use std::rc::Rc;
+
+    let mut map = FacetHashMap::<u32, Rc<String>>::default();
+    let value = Rc::new("v".to_string());
+    for key in 0..4 {
+        map.insert(key, value.clone());
+    }
+
+    let mut iter = map.into_iter();
+    assert_eq!(iter.len(), 4);
+    let (key, taken) = iter.next().unwrap();
+    assert!(key < 4);
+    assert_eq!(iter.len(), 3);
+    assert_eq!(Rc::strong_count(&value), 5);
+    drop(iter);
+    assert_eq!(Rc::strong_count(&value), 2);
+    drop(taken);
+
+    let mut map = FacetHashMap::<String, u32>::default();
+    map.insert("a".to_string(), 1);
+    map.insert("b".to_string(), 2);
+    let mut entries: Vec<(String, u32)> = map.into_iter().collect();
+    entries.sort();
+    assert_eq!(entries, [("a".to_string(), 1), ("b".to_string(), 2)]);
+}
+
+#[test]
+fn test_keys_and_values() {
+    let mut map = FacetHashMap::<String, u32>::default();
+    map.insert("a".to_string(), 1);
+    map.insert("bb".to_string(), 2);
+
+    let mut keys: Vec<_> = map.keys().cloned().collect();
+    keys.sort();
+    assert_eq!(keys, ["a", "bb"]);
+    assert_eq!(map.values().len(), 2);
+    assert_eq!(map.values().sum::<u32>(), 3);
+
+    for value in map.values_mut() {
+        *value *= 10;
+    }
+    assert_eq!(map.get(&"bb".to_string()), Some(&20));
+    assert_eq!(map.values().sum::<u32>(), 30);
+}
+
+#[test]
+fn test_into_keys_and_values() {
+    use std::rc::Rc;
+
+    let value = Rc::new(0u8);
+    let mut map = FacetHashMap::<String, Rc<u8>>::default();
+    map.insert("a".to_string(), value.clone());
+    map.insert("b".to_string(), value.clone());
+
+    let mut keys = map.into_keys();
+    assert_eq!(keys.len(), 2);
+    let key = keys.next().unwrap();
+    assert!(key == "a" || key == "b");
+    assert_eq!(Rc::strong_count(&value), 2);
+    drop(keys);
+    assert_eq!(Rc::strong_count(&value), 1);
+
+    let mut map = FacetHashMap::<Rc<u8>, String>::default();
+    map.insert(value.clone(), "v".to_string());
+    let values: Vec<String> = map.into_values().collect();
+    assert_eq!(values, ["v"]);
+    assert_eq!(Rc::strong_count(&value), 1);
+}
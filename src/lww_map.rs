@@ -0,0 +1,104 @@
+use std::borrow::Borrow;
+use std::hash::BuildHasher;
+
+use facet::Facet;
+
+use crate::facet_hashmap::FacetHashMap;
+use crate::storage::{DefaultStorage, StorageBackend};
+
+/// Orders writes to a [`LwwFacetMap`]: the later timestamp wins, and the
+/// actor id breaks ties so every replica picks the same winner.
+#[derive(Facet, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LwwTag {
+    pub timestamp: u64,
+    pub actor: u64,
+}
+
+/// The latest write for a key. Removals are kept as tombstones with no
+/// value, so that they win over older writes arriving later.
+#[derive(Facet, Debug)]
+pub struct LwwEntry<V> {
+    pub value: Option<V>,
+    pub tag: LwwTag,
+}
+
+/// A last-writer-wins map: every write carries an [`LwwTag`], and merging
+/// two replicas keeps the write with the greatest tag for each key, so
+/// replicas that have seen the same writes agree regardless of order.
+pub struct LwwFacetMap<
+    'a,
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S = hashbrown::DefaultHashBuilder,
+    B: StorageBackend = DefaultStorage,
+> {
+    entries: FacetHashMap<'a, K, LwwEntry<V>, S, B>,
+}
+
+impl<'a, K, V, S, B> Default for LwwFacetMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S: Default,
+    B: StorageBackend + Default,
+{
+    fn default() -> Self {
+        Self {
+            entries: FacetHashMap::default(),
+        }
+    }
+}
+
+impl<'a, K, V, S, B> LwwFacetMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S: BuildHasher,
+    B: StorageBackend,
+{
+    /// Sets `key` to `value`, unless the map already has a write for `key`
+    /// with a greater tag.
+    pub fn insert(&mut self, key: K, value: V, tag: LwwTag) {
+        self.write(key, Some(value), tag);
+    }
+
+    /// Removes `key` by writing a tombstone, unless the map already has a
+    /// write for `key` with a greater tag.
+    pub fn remove(&mut self, key: K, tag: LwwTag) {
+        self.write(key, None, tag);
+    }
+
+    fn write(&mut self, key: K, value: Option<V>, tag: LwwTag) {
+        self.entries
+            .extend_with([(key, LwwEntry { value, tag })], keep_latest);
+    }
+
+    pub fn get<Q: Borrow<K>>(&self, key: &Q) -> Option<&V> {
+        self.entries.get(key)?.value.as_ref()
+    }
+
+    /// The tag of the latest write for `key`, including removals.
+    pub fn tag<Q: Borrow<K>>(&self, key: &Q) -> Option<LwwTag> {
+        self.entries.get(key).map(|entry| entry.tag)
+    }
+
+    /// Folds in the writes of another replica, keeping the latest write for
+    /// each key.
+    pub fn merge(&mut self, other: Self)
+    where
+        S: Default,
+        B: Default,
+    {
+        let entries = std::mem::take(&mut self.entries);
+        self.entries = entries.merge(other.entries, |_, mut left, right| {
+            keep_latest(&mut left, right);
+            left
+        });
+    }
+}
+
+fn keep_latest<V>(current: &mut LwwEntry<V>, new: LwwEntry<V>) {
+    if new.tag > current.tag {
+        *current = new;
+    }
+}
@@ -0,0 +1,51 @@
+use std::hash::BuildHasher;
+
+use facet::Facet;
+
+use crate::facet_hashmap::FacetHashMap;
+use crate::storage::StorageBackend;
+
+/// Periodic housekeeping for a map, run by calling [`Maintenance::tick`]
+/// from the application's own loop, or from a background thread with
+/// `spawn_maintenance` (behind the `background-maintenance` feature).
+pub trait Maintenance {
+    fn tick(&mut self);
+}
+
+/// Compacts the map if its backend [`StorageBackend::reclaims_on_compact`],
+/// releasing memory freed by removals; otherwise does nothing.
+impl<'a, K, V, S, B> Maintenance for FacetHashMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S: BuildHasher,
+    B: StorageBackend,
+{
+    fn tick(&mut self) {
+        if self.storage().reclaims_on_compact() {
+            self.compact();
+        }
+    }
+}
+
+/// Calls `tick` on the map every `interval` until the map is dropped (or
+/// its mutex poisoned).
+#[cfg(feature = "background-maintenance")]
+pub fn spawn_maintenance<M: Maintenance + Send + 'static>(
+    map: &std::sync::Arc<std::sync::Mutex<M>>,
+    interval: std::time::Duration,
+) -> std::thread::JoinHandle<()> {
+    let map = std::sync::Arc::downgrade(map);
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(interval);
+            let Some(map) = map.upgrade() else {
+                return;
+            };
+            let Ok(mut map) = map.lock() else {
+                return;
+            };
+            map.tick();
+        }
+    })
+}
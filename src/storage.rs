@@ -0,0 +1,448 @@
+use std::alloc::Layout;
+use std::cell::{Cell, RefCell};
+use std::ptr::NonNull;
+
+use facet::Shape;
+
+use crate::erased::ErasedStorage;
+
+/// Decides where erased keys and values live and provides the memory for
+/// the boxed ones. Each map owns one backend, and every erased value in the
+/// map is created and freed through it.
+pub trait StorageBackend {
+    /// Must return the same answer every time it is called with the same shape.
+    fn storage_for(&self, shape: &Shape) -> ErasedStorage {
+        ErasedStorage::for_shape(shape)
+    }
+
+    /// # Safety
+    /// `layout` must have a non-zero size
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8;
+
+    /// # Safety
+    /// `ptr` must have been returned by `allocate` on this backend with the
+    /// same `layout`, and not deallocated since
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout);
+
+    /// Called when an inline key or value whose shape has a drop fn is
+    /// created. Inline values never go through `allocate`, so this is the
+    /// only way to keep track of them.
+    fn inline_created(&self) {}
+
+    /// Called when an inline value reported by `inline_created` has been
+    /// dropped or moved out.
+    fn inline_released(&self) {}
+
+    /// Whether [`crate::FacetHashMap::compact`] may move the boxed keys and
+    /// values to new addresses. Backends that promise stable addresses
+    /// return false.
+    fn relocatable(&self) -> bool {
+        true
+    }
+
+    /// Whether [`crate::FacetHashMap::compact`] gives memory back, because
+    /// the backend holds on to what removed entries freed. Periodic
+    /// [`crate::Maintenance`] only compacts maps whose backend does.
+    fn reclaims_on_compact(&self) -> bool {
+        false
+    }
+
+    /// An empty backend configured like this one, which
+    /// [`crate::FacetHashMap::compact`] moves the entries into.
+    fn fresh(&self) -> Self
+    where
+        Self: Sized;
+}
+
+/// Values that fit in a pointer are stored inline, everything else goes
+/// through the global allocator.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct DefaultStorage;
+
+impl StorageBackend for DefaultStorage {
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { std::alloc::alloc(layout) };
+        if ptr.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        ptr
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { std::alloc::dealloc(ptr, layout) }
+    }
+
+    fn fresh(&self) -> Self {
+        DefaultStorage
+    }
+}
+
+/// How a map's keys and values are stored, from
+/// [`crate::FacetHashMap::storage_report`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StorageReport {
+    pub inline: usize,
+    pub boxed: usize,
+    /// how many boxed keys and values there are of each size in bytes
+    pub boxed_sizes: std::collections::BTreeMap<usize, usize>,
+}
+
+impl StorageReport {
+    /// Counts `count` erased values of `shape` stored as `storage`.
+    pub fn record(&mut self, shape: &Shape, storage: ErasedStorage, count: usize) {
+        match storage {
+            ErasedStorage::Inline => self.inline += count,
+            ErasedStorage::Boxed => {
+                self.boxed += count;
+                let size = shape
+                    .layout
+                    .sized_layout()
+                    .map_or(0, |layout| layout.size());
+                *self.boxed_sizes.entry(size).or_default() += count;
+            }
+        }
+    }
+}
+
+const ARENA_CHUNK_SIZE: usize = 4096;
+
+/// Bump-allocates boxed values out of large chunks. Freeing a single value
+/// is a no-op; the chunks are released when the backend is dropped.
+#[derive(Default)]
+pub struct ArenaStorage {
+    chunks: RefCell<Vec<(NonNull<u8>, Layout)>>,
+    /// bytes used in the last chunk
+    used: Cell<usize>,
+}
+
+impl StorageBackend for ArenaStorage {
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        let mut chunks = self.chunks.borrow_mut();
+
+        if let Some((chunk, chunk_layout)) = chunks.last() {
+            let offset = align_offset(*chunk, self.used.get(), layout.align());
+            if offset + layout.size() <= chunk_layout.size() {
+                self.used.set(offset + layout.size());
+                return unsafe { chunk.as_ptr().add(offset) };
+            }
+        }
+
+        let chunk_layout = Layout::from_size_align(
+            ARENA_CHUNK_SIZE.max(layout.size()),
+            layout.align().max(std::mem::align_of::<usize>()),
+        )
+        .unwrap();
+        let chunk = unsafe { DefaultStorage.allocate(chunk_layout) };
+        let chunk = NonNull::new(chunk).unwrap();
+        chunks.push((chunk, chunk_layout));
+        self.used.set(layout.size());
+        chunk.as_ptr()
+    }
+
+    unsafe fn deallocate(&self, _ptr: *mut u8, _layout: Layout) {}
+
+    fn reclaims_on_compact(&self) -> bool {
+        true
+    }
+
+    fn fresh(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl ArenaStorage {
+    /// Reclaims everything allocated so far, keeping the most recent chunk
+    /// around for reuse. Values allocated from the arena must already have
+    /// been dropped.
+    pub fn reset(&mut self) {
+        let chunks = self.chunks.get_mut();
+        let last_chunk = chunks.pop();
+        for (chunk, layout) in chunks.drain(..) {
+            unsafe { DefaultStorage.deallocate(chunk.as_ptr(), layout) }
+        }
+        chunks.extend(last_chunk);
+        self.used.set(0);
+    }
+}
+
+impl Drop for ArenaStorage {
+    fn drop(&mut self) {
+        for (chunk, layout) in self.chunks.get_mut().drain(..) {
+            unsafe { DefaultStorage.deallocate(chunk.as_ptr(), layout) }
+        }
+    }
+}
+
+fn align_offset(chunk: NonNull<u8>, used: usize, align: usize) -> usize {
+    let addr = chunk.as_ptr() as usize + used;
+    used + (addr.next_multiple_of(align) - addr)
+}
+
+/// Keeps freed blocks on a per-layout free list and hands them out again
+/// instead of going back to the global allocator, which suits maps with
+/// heavy insert/remove churn.
+#[derive(Default)]
+pub struct PooledStorage {
+    free_lists: RefCell<hashbrown::HashMap<Layout, Vec<NonNull<u8>>>>,
+}
+
+impl StorageBackend for PooledStorage {
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        let reused = self
+            .free_lists
+            .borrow_mut()
+            .get_mut(&layout)
+            .and_then(|free_list| free_list.pop());
+
+        match reused {
+            Some(ptr) => ptr.as_ptr(),
+            None => unsafe { DefaultStorage.allocate(layout) },
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        self.free_lists
+            .borrow_mut()
+            .entry(layout)
+            .or_default()
+            .push(NonNull::new(ptr).unwrap());
+    }
+
+    fn reclaims_on_compact(&self) -> bool {
+        true
+    }
+
+    fn fresh(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl Drop for PooledStorage {
+    fn drop(&mut self) {
+        for (layout, free_list) in self.free_lists.get_mut().drain() {
+            for ptr in free_list {
+                unsafe { DefaultStorage.deallocate(ptr.as_ptr(), layout) }
+            }
+        }
+    }
+}
+
+/// Callbacks for [`HookedStorage`], e.g. to attribute a map's memory to a
+/// subsystem.
+pub trait AllocHooks {
+    fn on_allocate(&self, layout: Layout);
+    fn on_deallocate(&self, layout: Layout);
+}
+
+/// Forwards to another backend, calling `hooks` whenever a boxed key or
+/// value is allocated or freed. The hooks are shared with the backend's
+/// [`StorageBackend::fresh`] copies.
+#[derive(Default)]
+pub struct HookedStorage<H, B = DefaultStorage> {
+    hooks: std::sync::Arc<H>,
+    inner: B,
+}
+
+impl<H: AllocHooks, B> HookedStorage<H, B> {
+    pub fn new(hooks: H, inner: B) -> Self {
+        Self {
+            hooks: std::sync::Arc::new(hooks),
+            inner,
+        }
+    }
+
+    pub fn hooks(&self) -> &H {
+        &self.hooks
+    }
+}
+
+impl<H: AllocHooks, B: StorageBackend> StorageBackend for HookedStorage<H, B> {
+    fn storage_for(&self, shape: &Shape) -> ErasedStorage {
+        self.inner.storage_for(shape)
+    }
+
+    fn inline_created(&self) {
+        self.inner.inline_created()
+    }
+
+    fn inline_released(&self) {
+        self.inner.inline_released()
+    }
+
+    fn relocatable(&self) -> bool {
+        self.inner.relocatable()
+    }
+
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        self.hooks.on_allocate(layout);
+        unsafe { self.inner.allocate(layout) }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        self.hooks.on_deallocate(layout);
+        unsafe { self.inner.deallocate(ptr, layout) }
+    }
+
+    fn reclaims_on_compact(&self) -> bool {
+        self.inner.reclaims_on_compact()
+    }
+
+    fn fresh(&self) -> Self {
+        Self {
+            hooks: self.hooks.clone(),
+            inner: self.inner.fresh(),
+        }
+    }
+}
+
+/// Forwards to another backend but boxes every key and value, even ones
+/// that would fit inline, so their addresses stay put for as long as they
+/// are in the map (e.g. for handing them to FFI). Zero-sized values are
+/// still stored inline.
+#[derive(Default)]
+pub struct BoxedStorage<B = DefaultStorage> {
+    inner: B,
+}
+
+impl<B> BoxedStorage<B> {
+    pub fn new(inner: B) -> Self {
+        Self { inner }
+    }
+}
+
+impl<B: StorageBackend> StorageBackend for BoxedStorage<B> {
+    fn storage_for(&self, shape: &Shape) -> ErasedStorage {
+        match shape.layout.sized_layout() {
+            Ok(layout) if layout.size() == 0 => ErasedStorage::Inline,
+            _ => ErasedStorage::Boxed,
+        }
+    }
+
+    fn inline_created(&self) {
+        self.inner.inline_created()
+    }
+
+    fn inline_released(&self) {
+        self.inner.inline_released()
+    }
+
+    fn relocatable(&self) -> bool {
+        false
+    }
+
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        unsafe { self.inner.allocate(layout) }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.deallocate(ptr, layout) }
+    }
+
+    fn reclaims_on_compact(&self) -> bool {
+        self.inner.reclaims_on_compact()
+    }
+
+    fn fresh(&self) -> Self {
+        Self {
+            inner: self.inner.fresh(),
+        }
+    }
+}
+
+/// Wraps another backend and counts its live allocations and inline values,
+/// so tests can check that every key and value was freed or dropped.
+/// [`StorageBackend::fresh`] copies share the counts.
+#[cfg(feature = "leak-check")]
+#[derive(Default)]
+pub struct LeakCheckStorage<B = DefaultStorage> {
+    inner: B,
+    counter: LeakCounter,
+}
+
+/// A handle to the live counts of a [`LeakCheckStorage`], which stays
+/// usable after the storage (and its map) has been dropped.
+#[cfg(feature = "leak-check")]
+#[derive(Clone, Default, Debug)]
+pub struct LeakCounter {
+    allocations: std::rc::Rc<Cell<usize>>,
+    inline: std::rc::Rc<Cell<usize>>,
+}
+
+#[cfg(feature = "leak-check")]
+impl LeakCounter {
+    pub fn live_allocations(&self) -> usize {
+        self.allocations.get()
+    }
+
+    /// Inline keys and values that need dropping and haven't been yet.
+    pub fn live_inline(&self) -> usize {
+        self.inline.get()
+    }
+
+    pub fn assert_no_leaks(&self) {
+        let live = self.live_allocations();
+        assert_eq!(live, 0, "{live} erased allocations are still live");
+        let live = self.live_inline();
+        assert_eq!(live, 0, "{live} inline erased values are still live");
+    }
+}
+
+#[cfg(feature = "leak-check")]
+impl<B> LeakCheckStorage<B> {
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            counter: LeakCounter::default(),
+        }
+    }
+
+    pub fn counter(&self) -> LeakCounter {
+        self.counter.clone()
+    }
+}
+
+#[cfg(feature = "leak-check")]
+impl<B: StorageBackend> StorageBackend for LeakCheckStorage<B> {
+    fn storage_for(&self, shape: &Shape) -> ErasedStorage {
+        self.inner.storage_for(shape)
+    }
+
+    fn inline_created(&self) {
+        let inline = &self.counter.inline;
+        inline.set(inline.get() + 1);
+        self.inner.inline_created()
+    }
+
+    fn inline_released(&self) {
+        let inline = &self.counter.inline;
+        inline.set(inline.get() - 1);
+        self.inner.inline_released()
+    }
+
+    fn relocatable(&self) -> bool {
+        self.inner.relocatable()
+    }
+
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        let allocations = &self.counter.allocations;
+        allocations.set(allocations.get() + 1);
+        unsafe { self.inner.allocate(layout) }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        let allocations = &self.counter.allocations;
+        allocations.set(allocations.get() - 1);
+        unsafe { self.inner.deallocate(ptr, layout) }
+    }
+
+    fn reclaims_on_compact(&self) -> bool {
+        self.inner.reclaims_on_compact()
+    }
+
+    fn fresh(&self) -> Self {
+        Self {
+            inner: self.inner.fresh(),
+            counter: self.counter.clone(),
+        }
+    }
+}
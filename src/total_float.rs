@@ -0,0 +1,111 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use facet::{Def, Facet, Shape, Type, TypeParam, UserType, ValueVTable, value_vtable};
+
+/// An `f32` or `f64` that can be used as a key: `-0.0` is stored as `0.0`
+/// and every NaN as the same NaN, after which values are hashed by their
+/// bit pattern and compared with `total_cmp`.
+#[derive(Clone, Copy)]
+pub struct TotalFloat<F>(F);
+
+impl<F: sealed::Float> TotalFloat<F> {
+    pub fn new(value: F) -> Self {
+        Self(value.canonicalize())
+    }
+
+    pub fn get(self) -> F {
+        self.0
+    }
+}
+
+mod sealed {
+    pub trait Float: Copy {
+        fn canonicalize(self) -> Self;
+    }
+}
+
+macro_rules! impl_total_float {
+    ($float:ty, $name:literal) => {
+        impl sealed::Float for $float {
+            fn canonicalize(self) -> Self {
+                if self.is_nan() {
+                    <$float>::NAN
+                } else {
+                    // -0.0 + 0.0 is 0.0
+                    self + 0.0
+                }
+            }
+        }
+
+        impl From<$float> for TotalFloat<$float> {
+            fn from(value: $float) -> Self {
+                Self::new(value)
+            }
+        }
+
+        impl PartialEq for TotalFloat<$float> {
+            fn eq(&self, other: &Self) -> bool {
+                self.0.to_bits() == other.0.to_bits()
+            }
+        }
+
+        impl Eq for TotalFloat<$float> {}
+
+        impl PartialOrd for TotalFloat<$float> {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for TotalFloat<$float> {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
+
+        impl Hash for TotalFloat<$float> {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.0.to_bits().hash(state)
+            }
+        }
+
+        impl fmt::Debug for TotalFloat<$float> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&self.0, f)
+            }
+        }
+
+        impl fmt::Display for TotalFloat<$float> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Display::fmt(&self.0, f)
+            }
+        }
+
+        unsafe impl Facet<'_> for TotalFloat<$float> {
+            const VTABLE: &'static ValueVTable = &const {
+                value_vtable!(TotalFloat<$float>, |f, _opts| write!(
+                    f,
+                    "TotalFloat<{}>",
+                    $name
+                ))
+            };
+
+            const SHAPE: &'static Shape<'static> = &const {
+                Shape::builder_for_sized::<Self>()
+                    .type_identifier("TotalFloat")
+                    .type_params(&[TypeParam {
+                        name: "F",
+                        shape: || <$float>::SHAPE,
+                    }])
+                    .ty(Type::User(UserType::Opaque))
+                    .def(Def::Undefined)
+                    .build()
+            };
+        }
+    };
+}
+
+impl_total_float!(f32, "f32");
+impl_total_float!(f64, "f64");
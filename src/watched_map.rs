@@ -0,0 +1,128 @@
+use std::borrow::Borrow;
+use std::hash::BuildHasher;
+use std::sync::mpsc::{Receiver, Sender, channel};
+
+use facet::Facet;
+
+use crate::facet_hashmap::FacetHashMap;
+use crate::storage::{DefaultStorage, StorageBackend};
+
+/// A change to a [`WatchedFacetMap`], sent to the subscribers watching its key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent<K, V> {
+    Inserted { key: K, value: V },
+    Updated { key: K, old: V, new: V },
+    Removed { key: K, value: V },
+}
+
+struct Watcher<'a, K, V> {
+    filter: Box<dyn Fn(&K) -> bool + 'a>,
+    sender: Sender<WatchEvent<K, V>>,
+}
+
+/// A map that sends a [`WatchEvent`] with cloned keys and values to each
+/// subscriber whose filter matches the changed key. Subscribers are
+/// dropped once their receiver is.
+pub struct WatchedFacetMap<
+    'a,
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S = hashbrown::DefaultHashBuilder,
+    B: StorageBackend = DefaultStorage,
+> {
+    entries: FacetHashMap<'a, K, V, S, B>,
+    watchers: Vec<Watcher<'a, K, V>>,
+}
+
+impl<'a, K, V, S, B> Default for WatchedFacetMap<'a, K, V, S, B>
+where
+    K: Facet<'a>,
+    V: Facet<'a>,
+    S: Default,
+    B: StorageBackend + Default,
+{
+    fn default() -> Self {
+        Self {
+            entries: FacetHashMap::default(),
+            watchers: Vec::new(),
+        }
+    }
+}
+
+impl<'a, K, V, S, B> WatchedFacetMap<'a, K, V, S, B>
+where
+    K: Facet<'a> + Clone,
+    V: Facet<'a> + Clone,
+    S: BuildHasher,
+    B: StorageBackend,
+{
+    /// Receives the changes to every key that `filter` accepts.
+    pub fn subscribe(&mut self, filter: impl Fn(&K) -> bool + 'a) -> Receiver<WatchEvent<K, V>> {
+        let (sender, receiver) = channel();
+        self.watchers.push(Watcher {
+            filter: Box::new(filter),
+            sender,
+        });
+        receiver
+    }
+
+    /// Receives the changes to `key`.
+    pub fn subscribe_key(&mut self, key: K) -> Receiver<WatchEvent<K, V>>
+    where
+        K: PartialEq + 'a,
+    {
+        self.subscribe(move |changed| *changed == key)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if !self.is_watched(&key) {
+            return self.entries.insert(key, value);
+        }
+
+        let old = self.entries.insert(key.clone(), value.clone());
+        let event = match &old {
+            Some(old) => WatchEvent::Updated {
+                key,
+                old: old.clone(),
+                new: value,
+            },
+            None => WatchEvent::Inserted { key, value },
+        };
+        self.notify(event);
+        old
+    }
+
+    pub fn remove<Q: Borrow<K>>(&mut self, key: &Q) -> Option<V> {
+        let value = self.entries.remove(key)?;
+        if self.is_watched(key.borrow()) {
+            self.notify(WatchEvent::Removed {
+                key: key.borrow().clone(),
+                value: value.clone(),
+            });
+        }
+        Some(value)
+    }
+
+    pub fn get<Q: Borrow<K>>(&self, key: &Q) -> Option<&V> {
+        self.entries.get(key)
+    }
+
+    /// The underlying map, for reads that don't need to be watched.
+    pub fn entries(&self) -> &FacetHashMap<'a, K, V, S, B> {
+        &self.entries
+    }
+
+    fn is_watched(&self, key: &K) -> bool {
+        self.watchers.iter().any(|watcher| (watcher.filter)(key))
+    }
+
+    fn notify(&mut self, event: WatchEvent<K, V>) {
+        let key = match &event {
+            WatchEvent::Inserted { key, .. }
+            | WatchEvent::Updated { key, .. }
+            | WatchEvent::Removed { key, .. } => key,
+        };
+        self.watchers
+            .retain(|watcher| !(watcher.filter)(key) || watcher.sender.send(event.clone()).is_ok());
+    }
+}